@@ -0,0 +1,270 @@
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+use crate::crypto::CryptoBackend;
+
+/// The authentication types of the message control field according to EN13757-7.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive)]
+#[repr(u8)]
+pub enum AuthenticationType {
+    None = 0,
+    /// AES-128-CMAC truncated to 4 bytes.
+    AesCmac32 = 4,
+    /// AES-128-CMAC truncated to 8 bytes.
+    AesCmac64 = 5,
+    /// AES-128-CMAC truncated to 12 bytes.
+    AesCmac96 = 6,
+    /// AES-128-CMAC.
+    AesCmac128 = 7,
+    /// AES-128-GCM with a 12 byte tag.
+    AesGmac96 = 8,
+}
+
+impl AuthenticationType {
+    pub fn mac_size(self) -> usize {
+        match self {
+            AuthenticationType::None => 0,
+            AuthenticationType::AesCmac32 => 4,
+            AuthenticationType::AesCmac64 => 8,
+            AuthenticationType::AesCmac96 => 12,
+            AuthenticationType::AesCmac128 => 16,
+            AuthenticationType::AesGmac96 => 12,
+        }
+    }
+}
+
+/// The authentication and fragmentation layer (AFL) following the CI field 0x90.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuthenticationLayer {
+    /// The fragmentation control field.
+    pub fcl: u16,
+    /// The message control field.
+    pub mcl: Option<u8>,
+    /// The key information field.
+    pub ki: Option<u16>,
+    /// The message counter field.
+    pub mcr: Option<u32>,
+    pub mac: Option<Vec<u8>>,
+    /// The message length field.
+    pub ml: Option<u16>,
+}
+
+impl AuthenticationLayer {
+    pub const MORE_FRAGMENTS: u16 = 0x4000;
+    pub const MCL_PRESENT: u16 = 0x2000;
+    pub const ML_PRESENT: u16 = 0x1000;
+    pub const MCR_PRESENT: u16 = 0x0800;
+    pub const MAC_PRESENT: u16 = 0x0400;
+    pub const KI_PRESENT: u16 = 0x0200;
+
+    pub const ML_IN_MAC: u8 = 0x40;
+    pub const MCR_IN_MAC: u8 = 0x20;
+    pub const KI_IN_MAC: u8 = 0x10;
+
+    /// Parse the layer from the data following the CI field, i.e. starting with the AFLL field.
+    pub fn parse(data: &[u8]) -> Result<AuthenticationLayer, ()> {
+        let afll = *data.first().ok_or(())? as usize;
+        let fields = data.get(1..1 + afll).ok_or(())?;
+        if fields.len() < 2 {
+            return Err(());
+        }
+        let fcl = u16::from_le_bytes(fields[0..2].try_into().unwrap());
+        let mut rest = &fields[2..];
+
+        let mut take = |present: bool, size: usize| -> Result<Option<&[u8]>, ()> {
+            if !present {
+                return Ok(None);
+            }
+            if rest.len() < size {
+                return Err(());
+            }
+            let (field, tail) = rest.split_at(size);
+            rest = tail;
+            Ok(Some(field))
+        };
+
+        let mcl = take(fcl & Self::MCL_PRESENT != 0, 1)?.map(|x| x[0]);
+        let ki = take(fcl & Self::KI_PRESENT != 0, 2)?.map(|x| u16::from_le_bytes(x.try_into().unwrap()));
+        let mcr = take(fcl & Self::MCR_PRESENT != 0, 4)?.map(|x| u32::from_le_bytes(x.try_into().unwrap()));
+        let at: Option<AuthenticationType> = mcl.and_then(|x| num_traits::FromPrimitive::from_u8(x & 0x0F));
+        let mac_size = match at {
+            Some(at) => at.mac_size(),
+            _ if fcl & Self::MAC_PRESENT != 0 => return Err(()),
+            _ => 0,
+        };
+        let mac = take(fcl & Self::MAC_PRESENT != 0, mac_size)?.map(|x| x.to_vec());
+        let ml = take(fcl & Self::ML_PRESENT != 0, 2)?.map(|x| u16::from_le_bytes(x.try_into().unwrap()));
+        if !rest.is_empty() {
+            return Err(());
+        }
+
+        Ok(AuthenticationLayer {
+            fcl,
+            mcl,
+            ki,
+            mcr,
+            mac,
+            ml,
+        })
+    }
+
+    /// Get the size including the AFLL field.
+    pub fn size(&self) -> usize {
+        let mut size = 1 + 2;
+        if self.mcl.is_some() {
+            size += 1;
+        }
+        if self.ki.is_some() {
+            size += 2;
+        }
+        if self.mcr.is_some() {
+            size += 4;
+        }
+        if let Some(mac) = &self.mac {
+            size += mac.len();
+        }
+        if self.ml.is_some() {
+            size += 2;
+        }
+        size
+    }
+
+    pub fn more_fragments(&self) -> bool {
+        self.fcl & Self::MORE_FRAGMENTS != 0
+    }
+
+    pub fn fragment_id(&self) -> u8 {
+        self.fcl as u8
+    }
+
+    pub fn authentication_type(&self) -> Option<AuthenticationType> {
+        self.mcl.and_then(|mcl| num_traits::FromPrimitive::from_u8(mcl & 0x0F))
+    }
+
+    /// Compute the truncated AES-128-CMAC of the message following the layer, i.e. starting with its CI field.
+    pub fn compute_mac<B: CryptoBackend>(&self, backend: &B, key: &B::KeyHandle, message: &[u8]) -> Result<Vec<u8>, ()> {
+        let mcl = self.mcl.ok_or(())?;
+        let mac_size = match self.authentication_type() {
+            Some(at @ AuthenticationType::AesCmac32)
+            | Some(at @ AuthenticationType::AesCmac64)
+            | Some(at @ AuthenticationType::AesCmac96)
+            | Some(at @ AuthenticationType::AesCmac128) => at.mac_size(),
+            _ => return Err(()),
+        };
+
+        let mut input = Vec::with_capacity(1 + 2 + 4 + 2 + message.len());
+        input.push(mcl);
+        if mcl & Self::KI_IN_MAC != 0 {
+            input.extend_from_slice(&self.ki.ok_or(())?.to_le_bytes());
+        }
+        if mcl & Self::MCR_IN_MAC != 0 {
+            input.extend_from_slice(&self.mcr.ok_or(())?.to_le_bytes());
+        }
+        if mcl & Self::ML_IN_MAC != 0 {
+            input.extend_from_slice(&self.ml.ok_or(())?.to_le_bytes());
+        }
+        input.extend_from_slice(message);
+
+        Ok(backend.aes_cmac(key, &input)?[..mac_size].to_vec())
+    }
+
+    /// Verify the AES-128-CMAC of the message following the layer, i.e. starting with its CI field.
+    pub fn verify_mac<B: CryptoBackend>(&self, backend: &B, key: &B::KeyHandle, message: &[u8]) -> Result<(), ()> {
+        let expected = self.mac.as_ref().ok_or(())?;
+        if &self.compute_mac(backend, key, message)? != expected {
+            return Err(());
+        }
+        Ok(())
+    }
+
+    /// Write the layer starting with the AFLL field, where the presence bits of the FCL field follow the fields.
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        let mut fcl = self.fcl
+            & !(Self::MCL_PRESENT | Self::KI_PRESENT | Self::MCR_PRESENT | Self::MAC_PRESENT | Self::ML_PRESENT);
+        if self.mcl.is_some() {
+            fcl |= Self::MCL_PRESENT;
+        }
+        if self.ki.is_some() {
+            fcl |= Self::KI_PRESENT;
+        }
+        if self.mcr.is_some() {
+            fcl |= Self::MCR_PRESENT;
+        }
+        if self.mac.is_some() {
+            fcl |= Self::MAC_PRESENT;
+        }
+        if self.ml.is_some() {
+            fcl |= Self::ML_PRESENT;
+        }
+
+        buf.push((self.size() - 1) as u8);
+        buf.extend_from_slice(&fcl.to_le_bytes());
+        if let Some(mcl) = self.mcl {
+            buf.push(mcl);
+        }
+        if let Some(ki) = self.ki {
+            buf.extend_from_slice(&ki.to_le_bytes());
+        }
+        if let Some(mcr) = self.mcr {
+            buf.extend_from_slice(&mcr.to_le_bytes());
+        }
+        if let Some(mac) = &self.mac {
+            buf.extend_from_slice(mac);
+        }
+        if let Some(ml) = self.ml {
+            buf.extend_from_slice(&ml.to_le_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn parse_mode7() {
+        let afl = AuthenticationLayer::parse(&[
+            0x0F, 0x00, 0x2C, 0x25, 0x02, 0x01, 0x00, 0x00, 0x0C, 0x28, 0x90, 0x3B, 0x8D, 0xF3, 0xB5, 0xCB, 0x7A,
+        ])
+        .unwrap();
+        assert!(!afl.more_fragments());
+        assert_eq!(0, afl.fragment_id());
+        assert_eq!(Some(AuthenticationType::AesCmac64), afl.authentication_type());
+        assert_eq!(None, afl.ki);
+        assert_eq!(Some(0x0102), afl.mcr);
+        assert_eq!(
+            Some(vec![0x0C, 0x28, 0x90, 0x3B, 0x8D, 0xF3, 0xB5, 0xCB]),
+            afl.mac
+        );
+        assert_eq!(None, afl.ml);
+        assert_eq!(16, afl.size());
+    }
+
+    #[test]
+    pub fn parse_fragment() {
+        let afl = AuthenticationLayer::parse(&[0x04, 0x03, 0x50, 0x2A, 0x00]).unwrap();
+        assert!(afl.more_fragments());
+        assert_eq!(3, afl.fragment_id());
+        assert_eq!(None, afl.mcl);
+        assert_eq!(Some(42), afl.ml);
+        assert_eq!(5, afl.size());
+    }
+
+    #[test]
+    pub fn round_trip() {
+        let bytes = [
+            0x0F, 0x00, 0x2C, 0x25, 0x02, 0x01, 0x00, 0x00, 0x0C, 0x28, 0x90, 0x3B, 0x8D, 0xF3, 0xB5, 0xCB,
+        ];
+        let afl = AuthenticationLayer::parse(&bytes).unwrap();
+        let mut buf = Vec::new();
+        afl.write(&mut buf);
+        assert_eq!(&bytes, buf.as_slice());
+    }
+
+    #[test]
+    pub fn parse_truncated() {
+        assert!(AuthenticationLayer::parse(&[0x0F, 0x00, 0x2C, 0x25, 0x02, 0x01, 0x00, 0x00]).is_err());
+        // The MAC is present without a message control field
+        assert!(AuthenticationLayer::parse(&[0x02, 0x00, 0x04]).is_err());
+    }
+}
@@ -44,6 +44,10 @@ macro_rules! impl_bcd {
                 }
             }
 
+            pub fn bcd_value(self: Self) -> $type {
+                self.0
+            }
+
             pub fn decode(self: Self) -> $type {
                 let mut bcd = self.0;
                 let mut result = 0;
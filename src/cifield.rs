@@ -0,0 +1,242 @@
+/// The transport layer header following the CI field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransportHeader {
+    NoHeader,
+    Short,
+    Long,
+}
+
+/// The CI field according to EN13757-7.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CiField {
+    /// Application reset or select to device (0x50, 0x53).
+    ApplicationReset(TransportHeader),
+    /// Command to device (0x51, 0x5A, 0x5B).
+    Command(TransportHeader),
+    /// Selection of device (0x52).
+    Selection,
+    /// Request of selected application to device (0x54, 0x55).
+    ApplicationRequest(TransportHeader),
+    /// Synchronize action (0x5C).
+    SynchronizeAction,
+    /// DLMS/COSEM data to device (0x60, 0x61).
+    DlmsToDevice(TransportHeader),
+    /// SML/OBIS based data to device (0x64, 0x65).
+    SmlToDevice(TransportHeader),
+    /// Response of selected application from device (0x66, 0x67, 0x68).
+    ApplicationResponse(TransportHeader),
+    /// EN13757-3 application layer with format frame (0x69, 0x6A, 0x6B).
+    FormatFrame(TransportHeader),
+    /// Clock synchronisation, absolute (0x6C) or relative (0x6D).
+    ClockSynchronization { relative: bool },
+    /// Application error from device (0x70, 0x6E, 0x6F).
+    ApplicationError(TransportHeader),
+    /// Alarm from device (0x71, 0x74, 0x75).
+    Alarm(TransportHeader),
+    /// EN13757-3 application layer (0x78, 0x7A, 0x72).
+    ApplicationLayer(TransportHeader),
+    /// EN13757-3 application layer with compact frame (0x79, 0x7B, 0x73).
+    CompactFrame(TransportHeader),
+    /// DLMS/COSEM data from device (0x7D, 0x7C).
+    Dlms(TransportHeader),
+    /// SML/OBIS based data from device (0x7F, 0x7E).
+    Sml(TransportHeader),
+    /// Transport layer to device without application data (0x80).
+    TransportLayerToDevice,
+    /// Network layer (0x81).
+    NetworkLayer,
+    /// Transport layer from device without application data (0x8A, 0x8B).
+    TransportLayer(TransportHeader),
+    /// Extended link layer (0x8C, 0x8D, 0x8E, 0x8F).
+    ExtendedLinkLayer { long: bool, destination: bool },
+    /// Authentication and fragmentation layer (0x90).
+    AuthenticationFragmentationLayer,
+    /// Manufacturer specific (0xA0..=0xB7).
+    ManufacturerSpecific(u8),
+    Reserved(u8),
+}
+
+impl CiField {
+    /// Get the transport layer header implied by the CI field,
+    /// or None if the CI field does not denote a transport layer.
+    pub fn header(self) -> Option<TransportHeader> {
+        match self {
+            CiField::ApplicationReset(header)
+            | CiField::Command(header)
+            | CiField::ApplicationRequest(header)
+            | CiField::DlmsToDevice(header)
+            | CiField::SmlToDevice(header)
+            | CiField::ApplicationResponse(header)
+            | CiField::FormatFrame(header)
+            | CiField::ApplicationError(header)
+            | CiField::Alarm(header)
+            | CiField::ApplicationLayer(header)
+            | CiField::CompactFrame(header)
+            | CiField::Dlms(header)
+            | CiField::Sml(header)
+            | CiField::TransportLayer(header) => Some(header),
+            CiField::Selection | CiField::SynchronizeAction => Some(TransportHeader::NoHeader),
+            CiField::ClockSynchronization { .. } | CiField::TransportLayerToDevice => Some(TransportHeader::Long),
+            CiField::NetworkLayer
+            | CiField::ExtendedLinkLayer { .. }
+            | CiField::AuthenticationFragmentationLayer
+            | CiField::ManufacturerSpecific(_)
+            | CiField::Reserved(_) => None,
+        }
+    }
+}
+
+impl From<u8> for CiField {
+    fn from(ci: u8) -> Self {
+        use TransportHeader::*;
+        match ci {
+            0x50 => CiField::ApplicationReset(NoHeader),
+            0x51 => CiField::Command(NoHeader),
+            0x52 => CiField::Selection,
+            0x53 => CiField::ApplicationReset(Long),
+            0x54 => CiField::ApplicationRequest(NoHeader),
+            0x55 => CiField::ApplicationRequest(Long),
+            0x5A => CiField::Command(Short),
+            0x5B => CiField::Command(Long),
+            0x5C => CiField::SynchronizeAction,
+            0x60 => CiField::DlmsToDevice(Long),
+            0x61 => CiField::DlmsToDevice(Short),
+            0x64 => CiField::SmlToDevice(Long),
+            0x65 => CiField::SmlToDevice(Short),
+            0x66 => CiField::ApplicationResponse(NoHeader),
+            0x67 => CiField::ApplicationResponse(Short),
+            0x68 => CiField::ApplicationResponse(Long),
+            0x69 => CiField::FormatFrame(NoHeader),
+            0x6A => CiField::FormatFrame(Short),
+            0x6B => CiField::FormatFrame(Long),
+            0x6C => CiField::ClockSynchronization { relative: false },
+            0x6D => CiField::ClockSynchronization { relative: true },
+            0x6E => CiField::ApplicationError(Short),
+            0x6F => CiField::ApplicationError(Long),
+            0x70 => CiField::ApplicationError(NoHeader),
+            0x71 => CiField::Alarm(NoHeader),
+            0x72 => CiField::ApplicationLayer(Long),
+            0x73 => CiField::CompactFrame(Long),
+            0x74 => CiField::Alarm(Short),
+            0x75 => CiField::Alarm(Long),
+            0x78 => CiField::ApplicationLayer(NoHeader),
+            0x79 => CiField::CompactFrame(NoHeader),
+            0x7A => CiField::ApplicationLayer(Short),
+            0x7B => CiField::CompactFrame(Short),
+            0x7C => CiField::Dlms(Long),
+            0x7D => CiField::Dlms(Short),
+            0x7E => CiField::Sml(Long),
+            0x7F => CiField::Sml(Short),
+            0x80 => CiField::TransportLayerToDevice,
+            0x81 => CiField::NetworkLayer,
+            0x8A => CiField::TransportLayer(Short),
+            0x8B => CiField::TransportLayer(Long),
+            0x8C..=0x8F => CiField::ExtendedLinkLayer {
+                long: ci & 0x01 != 0,
+                destination: ci & 0x02 != 0,
+            },
+            0x90 => CiField::AuthenticationFragmentationLayer,
+            0xA0..=0xB7 => CiField::ManufacturerSpecific(ci),
+            _ => CiField::Reserved(ci),
+        }
+    }
+}
+
+impl From<CiField> for u8 {
+    fn from(ci: CiField) -> Self {
+        use TransportHeader::*;
+        match ci {
+            CiField::ApplicationReset(NoHeader) => 0x50,
+            CiField::Command(NoHeader) => 0x51,
+            CiField::Selection => 0x52,
+            CiField::ApplicationReset(_) => 0x53,
+            CiField::ApplicationRequest(NoHeader) => 0x54,
+            CiField::ApplicationRequest(_) => 0x55,
+            CiField::Command(Short) => 0x5A,
+            CiField::Command(Long) => 0x5B,
+            CiField::SynchronizeAction => 0x5C,
+            CiField::DlmsToDevice(Short) => 0x61,
+            CiField::DlmsToDevice(_) => 0x60,
+            CiField::SmlToDevice(Short) => 0x65,
+            CiField::SmlToDevice(_) => 0x64,
+            CiField::ApplicationResponse(NoHeader) => 0x66,
+            CiField::ApplicationResponse(Short) => 0x67,
+            CiField::ApplicationResponse(Long) => 0x68,
+            CiField::FormatFrame(NoHeader) => 0x69,
+            CiField::FormatFrame(Short) => 0x6A,
+            CiField::FormatFrame(Long) => 0x6B,
+            CiField::ClockSynchronization { relative: false } => 0x6C,
+            CiField::ClockSynchronization { relative: true } => 0x6D,
+            CiField::ApplicationError(Short) => 0x6E,
+            CiField::ApplicationError(Long) => 0x6F,
+            CiField::ApplicationError(NoHeader) => 0x70,
+            CiField::Alarm(NoHeader) => 0x71,
+            CiField::ApplicationLayer(Long) => 0x72,
+            CiField::CompactFrame(Long) => 0x73,
+            CiField::Alarm(Short) => 0x74,
+            CiField::Alarm(Long) => 0x75,
+            CiField::ApplicationLayer(NoHeader) => 0x78,
+            CiField::CompactFrame(NoHeader) => 0x79,
+            CiField::ApplicationLayer(Short) => 0x7A,
+            CiField::CompactFrame(Short) => 0x7B,
+            CiField::Dlms(Short) => 0x7D,
+            CiField::Dlms(_) => 0x7C,
+            CiField::Sml(Short) => 0x7F,
+            CiField::Sml(_) => 0x7E,
+            CiField::TransportLayerToDevice => 0x80,
+            CiField::NetworkLayer => 0x81,
+            CiField::TransportLayer(Long) => 0x8B,
+            CiField::TransportLayer(_) => 0x8A,
+            CiField::ExtendedLinkLayer { long, destination } => 0x8C | long as u8 | (destination as u8) << 1,
+            CiField::AuthenticationFragmentationLayer => 0x90,
+            CiField::ManufacturerSpecific(ci) => ci,
+            CiField::Reserved(ci) => ci,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn round_trip() {
+        for ci in 0..=0xFF {
+            assert_eq!(ci, u8::from(CiField::from(ci)));
+        }
+    }
+
+    #[test]
+    pub fn application_layer_headers() {
+        assert_eq!(CiField::ApplicationLayer(TransportHeader::NoHeader), CiField::from(0x78));
+        assert_eq!(CiField::ApplicationLayer(TransportHeader::Short), CiField::from(0x7A));
+        assert_eq!(CiField::ApplicationLayer(TransportHeader::Long), CiField::from(0x72));
+        assert_eq!(CiField::CompactFrame(TransportHeader::NoHeader), CiField::from(0x79));
+        assert_eq!(Some(TransportHeader::Short), CiField::from(0x6A).header());
+        assert_eq!(Some(TransportHeader::Long), CiField::from(0x6C).header());
+        assert_eq!(Some(TransportHeader::NoHeader), CiField::from(0x70).header());
+    }
+
+    #[test]
+    pub fn link_layers() {
+        assert_eq!(
+            CiField::ExtendedLinkLayer {
+                long: true,
+                destination: false
+            },
+            CiField::from(0x8D)
+        );
+        assert_eq!(CiField::AuthenticationFragmentationLayer, CiField::from(0x90));
+        assert_eq!(None, CiField::from(0x8F).header());
+        assert_eq!(None, CiField::from(0x81).header());
+    }
+
+    #[test]
+    pub fn manufacturer_specific_and_reserved() {
+        assert_eq!(CiField::ManufacturerSpecific(0xA0), CiField::from(0xA0));
+        assert_eq!(CiField::ManufacturerSpecific(0xB7), CiField::from(0xB7));
+        assert_eq!(CiField::Reserved(0xB8), CiField::from(0xB8));
+        assert_eq!(CiField::Reserved(0x00), CiField::from(0x00));
+        assert_eq!(None, CiField::from(0xA5).header());
+    }
+}
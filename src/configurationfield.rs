@@ -0,0 +1,162 @@
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+/// The security modes according to EN13757-7.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive)]
+#[repr(u8)]
+pub enum SecurityMode {
+    /// No encryption.
+    Mode0 = 0,
+    /// AES-128-CBC with IV.
+    Mode5 = 5,
+    /// AES-128-CBC with zero IV and ephemeral keys.
+    Mode7 = 7,
+    Mode9 = 9,
+    /// AES-128-GCM.
+    Mode10 = 10,
+    /// TLS.
+    Mode13 = 13,
+}
+
+/// The configuration field of the transport layer header.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConfigurationField {
+    pub bidirectional: bool,
+    pub accessibility: bool,
+    pub synchronous: bool,
+    pub mode: u8,
+    /// The number of encrypted 16 byte blocks.
+    pub encrypted_blocks: u8,
+    pub content: u8,
+    pub repeated_access: bool,
+    pub hop_counter: bool,
+    /// The extension present for security mode 7.
+    pub extension: Option<ConfigurationFieldExtension>,
+}
+
+/// The configuration field extension for security mode 7.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConfigurationFieldExtension(pub u8);
+
+impl ConfigurationFieldExtension {
+    pub fn new(kdf: u8, key_id: u8) -> Self {
+        Self((kdf & 0x03) << 4 | (key_id & 0x0F))
+    }
+
+    /// Get the key derivation function, where 1 is the KDF-A of EN13757-7.
+    pub fn kdf(self) -> u8 {
+        (self.0 >> 4) & 0x03
+    }
+
+    pub fn key_id(self) -> u8 {
+        self.0 & 0x0F
+    }
+}
+
+impl ConfigurationField {
+    pub fn parse(data: &[u8]) -> Result<ConfigurationField, ()> {
+        if data.len() < 2 {
+            return Err(());
+        }
+        let value = u16::from_le_bytes(data[0..2].try_into().unwrap());
+        let mode = ((value >> 8) & 0x1F) as u8;
+        let extension = if mode == SecurityMode::Mode7 as u8 {
+            Some(ConfigurationFieldExtension(*data.get(2).ok_or(())?))
+        } else {
+            None
+        };
+
+        Ok(ConfigurationField {
+            bidirectional: value & 0x8000 != 0,
+            accessibility: value & 0x4000 != 0,
+            synchronous: value & 0x2000 != 0,
+            mode,
+            encrypted_blocks: ((value >> 4) & 0x0F) as u8,
+            content: ((value >> 2) & 0x03) as u8,
+            repeated_access: value & 0x0002 != 0,
+            hop_counter: value & 0x0001 != 0,
+            extension,
+        })
+    }
+
+    pub fn security_mode(&self) -> Option<SecurityMode> {
+        num_traits::FromPrimitive::from_u8(self.mode)
+    }
+
+    pub fn size(&self) -> usize {
+        if self.extension.is_some() {
+            3
+        } else {
+            2
+        }
+    }
+
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        let value = (self.bidirectional as u16) << 15
+            | (self.accessibility as u16) << 14
+            | (self.synchronous as u16) << 13
+            | ((self.mode & 0x1F) as u16) << 8
+            | ((self.encrypted_blocks & 0x0F) as u16) << 4
+            | ((self.content & 0x03) as u16) << 2
+            | (self.repeated_access as u16) << 1
+            | self.hop_counter as u16;
+        buf.extend_from_slice(&value.to_le_bytes());
+        if let Some(extension) = self.extension {
+            buf.push(extension.0);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn parse_mode5() {
+        let cfg = ConfigurationField::parse(&[0x40, 0x25]).unwrap();
+        assert_eq!(Some(SecurityMode::Mode5), cfg.security_mode());
+        assert_eq!(4, cfg.encrypted_blocks);
+        assert!(cfg.synchronous);
+        assert!(!cfg.bidirectional);
+        assert!(!cfg.accessibility);
+        assert_eq!(0, cfg.content);
+        assert_eq!(None, cfg.extension);
+        assert_eq!(2, cfg.size());
+    }
+
+    #[test]
+    pub fn parse_mode7() {
+        let cfg = ConfigurationField::parse(&[0x2D, 0xC7, 0x15]).unwrap();
+        assert_eq!(Some(SecurityMode::Mode7), cfg.security_mode());
+        assert!(cfg.bidirectional);
+        assert!(cfg.accessibility);
+        assert!(!cfg.synchronous);
+        assert_eq!(2, cfg.encrypted_blocks);
+        assert_eq!(3, cfg.content);
+        assert!(!cfg.repeated_access);
+        assert!(cfg.hop_counter);
+        assert_eq!(Some(ConfigurationFieldExtension::new(1, 5)), cfg.extension);
+        assert_eq!(1, cfg.extension.unwrap().kdf());
+        assert_eq!(5, cfg.extension.unwrap().key_id());
+        assert_eq!(3, cfg.size());
+
+        assert!(ConfigurationField::parse(&[0x2D, 0xC7]).is_err());
+    }
+
+    #[test]
+    pub fn round_trip() {
+        for bytes in [[0x40, 0x25, 0x00], [0x2D, 0xC7, 0x15], [0x00, 0x00, 0x00], [0xFF, 0xED, 0x00]] {
+            let cfg = ConfigurationField::parse(&bytes).unwrap();
+            let mut buf = Vec::new();
+            cfg.write(&mut buf);
+            assert_eq!(&bytes[..cfg.size()], buf.as_slice());
+        }
+    }
+
+    #[test]
+    pub fn unknown_security_mode() {
+        let cfg = ConfigurationField::parse(&[0x00, 0x03]).unwrap();
+        assert_eq!(3, cfg.mode);
+        assert_eq!(None, cfg.security_mode());
+    }
+}
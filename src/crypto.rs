@@ -0,0 +1,352 @@
+use aes::{
+    cipher::{consts::U12, BlockDecryptMut, BlockEncryptMut, KeyIvInit, StreamCipher},
+    Aes128,
+};
+use aes_gcm::{aead::AeadInPlace, AesGcm, KeyInit};
+use cmac::{Cmac, Mac};
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+type Aes128CbcEnc = cbc::Encryptor<Aes128>;
+type Aes128CbcDec = cbc::Decryptor<Aes128>;
+type Aes128Gcm = AesGcm<Aes128, U12, U12>;
+
+pub const BLOCK_SIZE: usize = 16;
+
+pub type Key = [u8; 16];
+pub type Iv = [u8; 16];
+
+/// The derivation constant selecting the key derived by the key derivation function.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum DerivationConstant {
+    /// The encryption key for messages from the meter.
+    Kenc = 0x00,
+    /// The MAC key for messages from the meter.
+    Kmac = 0x01,
+}
+
+/// The AES-128 operations used by the security modes.
+/// The keys are referenced by handles so that they can be kept in a secure element or a hardware engine.
+pub trait CryptoBackend {
+    type KeyHandle;
+
+    /// Apply the AES-128-CTR keystream to data in place.
+    /// The counter is the big endian 128 bit value of the iv, i.e. the last byte is the block counter.
+    fn aes_ctr(&self, key: &Self::KeyHandle, iv: &Iv, data: &mut [u8]) -> Result<(), ()>;
+
+    /// Encrypt AES-128-CBC data in place, the length of the data must be a multiple of the block size.
+    fn aes_cbc_encrypt(&self, key: &Self::KeyHandle, iv: &Iv, data: &mut [u8]) -> Result<(), ()>;
+
+    /// Decrypt AES-128-CBC data in place, the length of the data must be a multiple of the block size.
+    fn aes_cbc_decrypt(&self, key: &Self::KeyHandle, iv: &Iv, data: &mut [u8]) -> Result<(), ()>;
+
+    /// Decrypt AES-128-GCM data in place and verify the 12 byte authentication tag.
+    /// The data must be left encrypted if the tag does not match.
+    fn aes_gcm_decrypt(
+        &self,
+        key: &Self::KeyHandle,
+        iv: &[u8; 12],
+        aad: &[u8],
+        data: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), ()>;
+
+    /// Compute the AES-128-CMAC of data.
+    fn aes_cmac(&self, key: &Self::KeyHandle, data: &[u8]) -> Result<[u8; 16], ()>;
+
+    /// Derive an ephemeral key from the master key using the KDF-A of EN13757-7,
+    /// i.e. the CMAC of the input given by `kdf_input()`.
+    fn derive_key(
+        &self,
+        master: &Self::KeyHandle,
+        constant: DerivationConstant,
+        counter: u32,
+        id: [u8; 4],
+    ) -> Result<Self::KeyHandle, ()>;
+}
+
+/// Get the KDF-A input, i.e. the derivation constant, the message counter, the meter id and the padding.
+pub fn kdf_input(constant: DerivationConstant, counter: u32, id: [u8; 4]) -> [u8; 16] {
+    let mut input = [0x07; 16];
+    input[0] = constant as u8;
+    input[1..5].copy_from_slice(&counter.to_le_bytes());
+    input[5..9].copy_from_slice(&id);
+    input
+}
+
+/// The software reference implementation where the key handles are the key bytes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SoftwareCrypto;
+
+impl CryptoBackend for SoftwareCrypto {
+    type KeyHandle = Key;
+
+    fn aes_ctr(&self, key: &Key, iv: &Iv, data: &mut [u8]) -> Result<(), ()> {
+        let mut cipher = Aes128Ctr::new(key.into(), iv.into());
+        cipher.apply_keystream(data);
+        Ok(())
+    }
+
+    fn aes_cbc_encrypt(&self, key: &Key, iv: &Iv, data: &mut [u8]) -> Result<(), ()> {
+        if data.len() % BLOCK_SIZE != 0 {
+            return Err(());
+        }
+        let mut cipher = Aes128CbcEnc::new(key.into(), iv.into());
+        for block in data.chunks_exact_mut(BLOCK_SIZE) {
+            cipher.encrypt_block_mut(block.into());
+        }
+        Ok(())
+    }
+
+    fn aes_cbc_decrypt(&self, key: &Key, iv: &Iv, data: &mut [u8]) -> Result<(), ()> {
+        if data.len() % BLOCK_SIZE != 0 {
+            return Err(());
+        }
+        let mut cipher = Aes128CbcDec::new(key.into(), iv.into());
+        for block in data.chunks_exact_mut(BLOCK_SIZE) {
+            cipher.decrypt_block_mut(block.into());
+        }
+        Ok(())
+    }
+
+    fn aes_gcm_decrypt(&self, key: &Key, iv: &[u8; 12], aad: &[u8], data: &mut [u8], tag: &[u8]) -> Result<(), ()> {
+        if tag.len() != 12 {
+            return Err(());
+        }
+        let cipher = Aes128Gcm::new(key.into());
+        cipher
+            .decrypt_in_place_detached(iv.into(), aad, data, tag.into())
+            .map_err(|_| ())
+    }
+
+    fn aes_cmac(&self, key: &Key, data: &[u8]) -> Result<[u8; 16], ()> {
+        let mut mac = <Cmac<Aes128> as Mac>::new(key.into());
+        mac.update(data);
+        Ok(mac.finalize().into_bytes().into())
+    }
+
+    fn derive_key(&self, master: &Key, constant: DerivationConstant, counter: u32, id: [u8; 4]) -> Result<Key, ()> {
+        self.aes_cmac(master, &kdf_input(constant, counter, id))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use alloc::vec::Vec;
+    use core::cell::{Cell, RefCell};
+
+    use super::*;
+
+    /// A backend where the keys are kept in a table and referenced by their index, as in a secure element.
+    #[derive(Default)]
+    pub struct MockCrypto {
+        keys: RefCell<Vec<Key>>,
+        /// The number of operations performed.
+        pub operations: Cell<usize>,
+    }
+
+    impl MockCrypto {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Store the key and get its handle.
+        pub fn add_key(&self, key: Key) -> usize {
+            let mut keys = self.keys.borrow_mut();
+            keys.push(key);
+            keys.len() - 1
+        }
+
+        fn key(&self, handle: &usize) -> Result<Key, ()> {
+            self.operations.set(self.operations.get() + 1);
+            self.keys.borrow().get(*handle).copied().ok_or(())
+        }
+    }
+
+    impl CryptoBackend for MockCrypto {
+        type KeyHandle = usize;
+
+        fn aes_ctr(&self, key: &usize, iv: &Iv, data: &mut [u8]) -> Result<(), ()> {
+            SoftwareCrypto.aes_ctr(&self.key(key)?, iv, data)
+        }
+
+        fn aes_cbc_encrypt(&self, key: &usize, iv: &Iv, data: &mut [u8]) -> Result<(), ()> {
+            SoftwareCrypto.aes_cbc_encrypt(&self.key(key)?, iv, data)
+        }
+
+        fn aes_cbc_decrypt(&self, key: &usize, iv: &Iv, data: &mut [u8]) -> Result<(), ()> {
+            SoftwareCrypto.aes_cbc_decrypt(&self.key(key)?, iv, data)
+        }
+
+        fn aes_gcm_decrypt(
+            &self,
+            key: &usize,
+            iv: &[u8; 12],
+            aad: &[u8],
+            data: &mut [u8],
+            tag: &[u8],
+        ) -> Result<(), ()> {
+            SoftwareCrypto.aes_gcm_decrypt(&self.key(key)?, iv, aad, data, tag)
+        }
+
+        fn aes_cmac(&self, key: &usize, data: &[u8]) -> Result<[u8; 16], ()> {
+            SoftwareCrypto.aes_cmac(&self.key(key)?, data)
+        }
+
+        fn derive_key(&self, master: &usize, constant: DerivationConstant, counter: u32, id: [u8; 4]) -> Result<usize, ()> {
+            let key = SoftwareCrypto.derive_key(&self.key(master)?, constant, counter, id)?;
+            Ok(self.add_key(key))
+        }
+    }
+
+    const SP800_38A_KEY: Key = [
+        0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF, 0x4F, 0x3C,
+    ];
+
+    #[test]
+    pub fn aes_cbc_encrypt_sp800_38a() {
+        // F.2.1 in NIST SP 800-38A
+        let iv = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F,
+        ];
+        let mut data = [
+            0x6B, 0xC1, 0xBE, 0xE2, 0x2E, 0x40, 0x9F, 0x96, 0xE9, 0x3D, 0x7E, 0x11, 0x73, 0x93, 0x17, 0x2A,
+        ];
+        SoftwareCrypto.aes_cbc_encrypt(&SP800_38A_KEY, &iv, &mut data).unwrap();
+        assert_eq!(
+            [0x76, 0x49, 0xAB, 0xAC, 0x81, 0x19, 0xB2, 0x46, 0xCE, 0xE9, 0x8E, 0x9B, 0x12, 0xE9, 0x19, 0x7D],
+            data
+        );
+
+        assert!(SoftwareCrypto.aes_cbc_encrypt(&SP800_38A_KEY, &iv, &mut data[..15]).is_err());
+    }
+
+    #[test]
+    pub fn aes_cbc_decrypt_sp800_38a() {
+        // F.2.2 in NIST SP 800-38A
+        let iv = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F,
+        ];
+        let mut data = [
+            0x76, 0x49, 0xAB, 0xAC, 0x81, 0x19, 0xB2, 0x46, 0xCE, 0xE9, 0x8E, 0x9B, 0x12, 0xE9, 0x19, 0x7D,
+        ];
+        SoftwareCrypto.aes_cbc_decrypt(&SP800_38A_KEY, &iv, &mut data).unwrap();
+        assert_eq!(
+            [0x6B, 0xC1, 0xBE, 0xE2, 0x2E, 0x40, 0x9F, 0x96, 0xE9, 0x3D, 0x7E, 0x11, 0x73, 0x93, 0x17, 0x2A],
+            data
+        );
+
+        assert!(SoftwareCrypto.aes_cbc_decrypt(&SP800_38A_KEY, &iv, &mut data[..15]).is_err());
+    }
+
+    #[test]
+    pub fn aes_gcm_decrypt_with_truncated_tag() {
+        let key = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F,
+        ];
+        let iv = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B];
+        let tag = [0xA6, 0xC7, 0x55, 0x79, 0x4B, 0x63, 0x33, 0xF9, 0x98, 0x56, 0x92, 0x14];
+        let encrypted = [
+            0x93, 0x6D, 0xA5, 0xCD, 0x62, 0x1E, 0xF1, 0x53, 0x43, 0xDB, 0x6B, 0x81, 0x3A, 0xAE, 0x7E, 0x07,
+        ];
+
+        let mut data = encrypted;
+        assert!(SoftwareCrypto.aes_gcm_decrypt(&key, &iv, &[0x01, 0x03], &mut data, &tag).is_err());
+        assert_eq!(encrypted, data);
+
+        SoftwareCrypto.aes_gcm_decrypt(&key, &iv, &[0x01, 0x02], &mut data, &tag).unwrap();
+        assert_eq!(key, data);
+    }
+
+    #[test]
+    pub fn aes_gcm_decrypt_test_case_4() {
+        // Test case 4 of The Galois/Counter Mode of Operation (GCM), McGrew and Viega, with the tag truncated to 96 bits
+        let key = [
+            0xFE, 0xFF, 0xE9, 0x92, 0x86, 0x65, 0x73, 0x1C, 0x6D, 0x6A, 0x8F, 0x94, 0x67, 0x30, 0x83, 0x08,
+        ];
+        let iv = [0xCA, 0xFE, 0xBA, 0xBE, 0xFA, 0xCE, 0xDB, 0xAD, 0xDE, 0xCA, 0xF8, 0x88];
+        let aad = [
+            0xFE, 0xED, 0xFA, 0xCE, 0xDE, 0xAD, 0xBE, 0xEF, 0xFE, 0xED, 0xFA, 0xCE, 0xDE, 0xAD, 0xBE, 0xEF, 0xAB, 0xAD,
+            0xDA, 0xD2,
+        ];
+        let tag = [0x5B, 0xC9, 0x4F, 0xBC, 0x32, 0x21, 0xA5, 0xDB, 0x94, 0xFA, 0xE9, 0x5A];
+        let mut data = [
+            0x42, 0x83, 0x1E, 0xC2, 0x21, 0x77, 0x74, 0x24, 0x4B, 0x72, 0x21, 0xB7, 0x84, 0xD0, 0xD4, 0x9C, 0xE3, 0xAA,
+            0x21, 0x2F, 0x2C, 0x02, 0xA4, 0xE0, 0x35, 0xC1, 0x7E, 0x23, 0x29, 0xAC, 0xA1, 0x2E, 0x21, 0xD5, 0x14, 0xB2,
+            0x54, 0x66, 0x93, 0x1C, 0x7D, 0x8F, 0x6A, 0x5A, 0xAC, 0x84, 0xAA, 0x05, 0x1B, 0xA3, 0x0B, 0x39, 0x6A, 0x0A,
+            0xAC, 0x97, 0x3D, 0x58, 0xE0, 0x91,
+        ];
+
+        SoftwareCrypto.aes_gcm_decrypt(&key, &iv, &aad, &mut data, &tag).unwrap();
+        assert_eq!(
+            [
+                0xD9, 0x31, 0x32, 0x25, 0xF8, 0x84, 0x06, 0xE5, 0xA5, 0x59, 0x09, 0xC5, 0xAF, 0xF5, 0x26, 0x9A, 0x86, 0xA7,
+                0xA9, 0x53, 0x15, 0x34, 0xF7, 0xDA, 0x2E, 0x4C, 0x30, 0x3D, 0x8A, 0x31, 0x8A, 0x72, 0x1C, 0x3C, 0x0C, 0x95,
+                0x95, 0x68, 0x09, 0x53, 0x2F, 0xCF, 0x0E, 0x24, 0x49, 0xA6, 0xB5, 0x25, 0xB1, 0x6A, 0xED, 0xF5, 0xAA, 0x0D,
+                0xE6, 0x57, 0xBA, 0x63, 0x7B, 0x39,
+            ],
+            data
+        );
+    }
+
+    #[test]
+    pub fn aes_cmac_rfc4493() {
+        // Example 2 in RFC 4493
+        let data = [
+            0x6B, 0xC1, 0xBE, 0xE2, 0x2E, 0x40, 0x9F, 0x96, 0xE9, 0x3D, 0x7E, 0x11, 0x73, 0x93, 0x17, 0x2A,
+        ];
+        assert_eq!(
+            [0x07, 0x0A, 0x16, 0xB4, 0x6B, 0x4D, 0x41, 0x44, 0xF7, 0x9B, 0xDD, 0x9D, 0xD0, 0x4A, 0x28, 0x7C],
+            SoftwareCrypto.aes_cmac(&SP800_38A_KEY, &data).unwrap()
+        );
+    }
+
+    #[test]
+    pub fn derive_keys() {
+        let master = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F,
+        ];
+        let id = [0x78, 0x56, 0x34, 0x12];
+        assert_eq!(
+            [0x28, 0xCA, 0xEA, 0x5B, 0xA8, 0xEF, 0xDF, 0x95, 0x11, 0x17, 0xDB, 0x39, 0x38, 0x4F, 0x20, 0xA1],
+            SoftwareCrypto.derive_key(&master, DerivationConstant::Kenc, 0x0102, id).unwrap()
+        );
+        assert_eq!(
+            [0x8B, 0xA4, 0xCE, 0x77, 0x48, 0xF8, 0xFE, 0xCD, 0xCB, 0x47, 0xF8, 0xCA, 0x15, 0x97, 0xA0, 0xB9],
+            SoftwareCrypto.derive_key(&master, DerivationConstant::Kmac, 0x0102, id).unwrap()
+        );
+    }
+
+    #[test]
+    pub fn aes_ctr_sp800_38a() {
+        // F.5.1 in NIST SP 800-38A
+        let iv = [
+            0xF0, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8, 0xF9, 0xFA, 0xFB, 0xFC, 0xFD, 0xFE, 0xFF,
+        ];
+        let mut data = [
+            0x6B, 0xC1, 0xBE, 0xE2, 0x2E, 0x40, 0x9F, 0x96, 0xE9, 0x3D, 0x7E, 0x11, 0x73, 0x93, 0x17, 0x2A,
+        ];
+        SoftwareCrypto.aes_ctr(&SP800_38A_KEY, &iv, &mut data).unwrap();
+        assert_eq!(
+            [0x87, 0x4D, 0x61, 0x91, 0xB6, 0x20, 0xE3, 0x26, 0x1B, 0xEF, 0x68, 0x64, 0x99, 0x0D, 0xB6, 0xCE],
+            data
+        );
+    }
+
+    #[test]
+    pub fn mock_resolves_key_handles() {
+        let mock = MockCrypto::new();
+        let handle = mock.add_key(SP800_38A_KEY);
+        let iv = [0; 16];
+        let mut data = [0; 16];
+        let mut expected = [0; 16];
+        mock.aes_cbc_encrypt(&handle, &iv, &mut data).unwrap();
+        SoftwareCrypto.aes_cbc_encrypt(&SP800_38A_KEY, &iv, &mut expected).unwrap();
+        assert_eq!(expected, data);
+
+        let derived = mock.derive_key(&handle, DerivationConstant::Kenc, 1, [0; 4]).unwrap();
+        assert_eq!(1, derived);
+        assert!(mock.aes_cmac(&2, &data).is_err());
+        assert_eq!(3, mock.operations.get());
+    }
+}
@@ -0,0 +1,818 @@
+use alloc::{string::String, vec::Vec};
+
+use crate::{
+    datetime::{Date, DateTime, DaylightSaving, Time},
+    value::Value,
+    vif::{Quantity, ValueInformation, VifModifier},
+};
+
+const EXTENSION: u8 = 0x80;
+const MAX_EXTENSIONS: usize = 10;
+
+const DIF_STORAGE_NUMBER: u8 = 0x40;
+const DIF_FUNCTION_SHIFT: u8 = 4;
+const DIF_DATA_FIELD_MASK: u8 = 0x0F;
+
+const DIFE_SUBUNIT: u8 = 0x40;
+const DIFE_TARIFF_SHIFT: u8 = 4;
+const DIFE_STORAGE_NUMBER_MASK: u8 = 0x0F;
+
+const VIF_PLAIN_TEXT: u8 = 0x7C;
+const VIF_FIRST_EXTENSION: u8 = 0xFD;
+const VIF_SECOND_EXTENSION: u8 = 0xFB;
+const VIF_MANUFACTURER_SPECIFIC: u8 = 0x7F;
+
+/// The special function DIF of manufacturer specific data until the end of the application layer.
+pub const DIF_MANUFACTURER_DATA: u8 = 0x0F;
+/// The special function DIF of manufacturer specific data, where more records follow in the next telegram.
+pub const DIF_MORE_RECORDS: u8 = 0x1F;
+/// The special function DIF of the idle filler.
+pub const DIF_IDLE_FILLER: u8 = 0x2F;
+/// The special function DIF of the global readout request.
+pub const DIF_GLOBAL_READOUT: u8 = 0x7F;
+
+/// The data field of the DIF, i.e. the coding and size of the data according to EN13757-3.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive)]
+#[repr(u8)]
+pub enum DataField {
+    NoData = 0x0,
+    Integer8 = 0x1,
+    Integer16 = 0x2,
+    Integer24 = 0x3,
+    Integer32 = 0x4,
+    Real32 = 0x5,
+    Integer48 = 0x6,
+    Integer64 = 0x7,
+    /// Selection for readout, i.e. no data.
+    SelectionForReadout = 0x8,
+    Bcd2 = 0x9,
+    Bcd4 = 0xA,
+    Bcd6 = 0xB,
+    Bcd8 = 0xC,
+    /// Variable length data, where the size follows in the LVAR byte.
+    VariableLength = 0xD,
+    Bcd12 = 0xE,
+    SpecialFunctions = 0xF,
+}
+
+impl DataField {
+    /// Get the size of the data, or None for variable length data and special functions.
+    pub fn size(self) -> Option<usize> {
+        match self {
+            DataField::NoData | DataField::SelectionForReadout => Some(0),
+            DataField::Integer8 | DataField::Bcd2 => Some(1),
+            DataField::Integer16 | DataField::Bcd4 => Some(2),
+            DataField::Integer24 | DataField::Bcd6 => Some(3),
+            DataField::Integer32 | DataField::Real32 | DataField::Bcd8 => Some(4),
+            DataField::Integer48 | DataField::Bcd12 => Some(6),
+            DataField::Integer64 => Some(8),
+            DataField::VariableLength | DataField::SpecialFunctions => None,
+        }
+    }
+}
+
+/// The function field of the DIF.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive)]
+#[repr(u8)]
+pub enum Function {
+    Instantaneous = 0,
+    Maximum = 1,
+    Minimum = 2,
+    /// Value during error state.
+    DuringError = 3,
+}
+
+/// A data record of the variable data structure according to EN13757-3.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataRecord {
+    /// The data information field.
+    pub dif: u8,
+    /// The data information field extensions.
+    pub dife: Vec<u8>,
+    /// The primary value information field.
+    pub vif: u8,
+    /// The value information field extensions, starting with the VIF of the extension table for VIF 0xFB and 0xFD.
+    pub vife: Vec<u8>,
+    /// The ASCII unit following the VIF extensions for the plain text VIF 0x7C and 0xFC, in transmitted order.
+    pub vif_text: Option<Vec<u8>>,
+    /// The data, including the LVAR byte for variable length data.
+    pub data: Vec<u8>,
+}
+
+/// The typed content of a data record, e.g. for encoding the records of a meter simulator or a command.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypedRecord {
+    pub value_information: ValueInformation,
+    pub function: Function,
+    pub storage_number: u64,
+    pub tariff: u32,
+    pub subunit: u16,
+    /// The combinable VIFEs other than the multiplicative correction factors, which are applied to the exponent.
+    /// The value is as transmitted, so an additive correction constant must be added to it by the caller.
+    pub modifiers: Vec<VifModifier>,
+    pub value: Value,
+}
+
+/// The data records of the application layer.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DataRecords {
+    pub records: Vec<DataRecord>,
+    /// The manufacturer specific data following the special function DIF 0x0F or 0x1F.
+    pub manufacturer_data: Option<Vec<u8>>,
+    /// Whether more records follow in the next telegram, i.e. the special function DIF 0x1F.
+    pub more_records: bool,
+    /// Whether the global readout request DIF 0x7F is present.
+    pub global_readout: bool,
+}
+
+impl DataRecord {
+    /// Parse a record from the start of the data, and get it together with its size.
+    /// The DIF must not be a special function.
+    pub fn parse(data: &[u8]) -> Result<(DataRecord, usize), ()> {
+        let mut index = 0;
+        let mut next = || -> Result<u8, ()> {
+            let byte = *data.get(index).ok_or(())?;
+            index += 1;
+            Ok(byte)
+        };
+
+        let dif = next()?;
+        if dif & DIF_DATA_FIELD_MASK == DataField::SpecialFunctions as u8 {
+            return Err(());
+        }
+        let mut dife = Vec::new();
+        let mut extension = dif & EXTENSION != 0;
+        while extension {
+            if dife.len() == MAX_EXTENSIONS {
+                return Err(());
+            }
+            let byte = next()?;
+            dife.push(byte);
+            extension = byte & EXTENSION != 0;
+        }
+
+        let vif = next()?;
+        let mut vife = Vec::new();
+        let mut extension = vif & EXTENSION != 0;
+        while extension {
+            if vife.len() == MAX_EXTENSIONS {
+                return Err(());
+            }
+            let byte = next()?;
+            vife.push(byte);
+            extension = byte & EXTENSION != 0;
+        }
+
+        let vif_text = if vif & !EXTENSION == VIF_PLAIN_TEXT {
+            let length = next()? as usize;
+            let text = data.get(index..index + length).ok_or(())?;
+            index += length;
+            Some(text.to_vec())
+        } else {
+            None
+        };
+
+        let data_field: DataField = num_traits::FromPrimitive::from_u8(dif & DIF_DATA_FIELD_MASK).unwrap();
+        let start = index;
+        let size = match data_field.size() {
+            Some(size) => size,
+            None => 1 + lvar_size(*data.get(start).ok_or(())?)?,
+        };
+        let value = data.get(start..start + size).ok_or(())?;
+
+        let record = DataRecord {
+            dif,
+            dife,
+            vif,
+            vife,
+            vif_text,
+            data: value.to_vec(),
+        };
+        Ok((record, start + size))
+    }
+
+    pub fn data_field(&self) -> DataField {
+        num_traits::FromPrimitive::from_u8(self.dif & DIF_DATA_FIELD_MASK).unwrap()
+    }
+
+    pub fn function(&self) -> Function {
+        num_traits::FromPrimitive::from_u8((self.dif >> DIF_FUNCTION_SHIFT) & 0x03).unwrap()
+    }
+
+    /// Get the storage number from the DIF and the DIFE chain, where 0 is the current value.
+    pub fn storage_number(&self) -> u64 {
+        self.dife.iter().enumerate().fold(
+            ((self.dif & DIF_STORAGE_NUMBER) >> 6) as u64,
+            |storage_number, (index, dife)| {
+                storage_number | ((dife & DIFE_STORAGE_NUMBER_MASK) as u64) << (1 + 4 * index)
+            },
+        )
+    }
+
+    pub fn tariff(&self) -> u32 {
+        self.dife.iter().enumerate().fold(0, |tariff, (index, dife)| {
+            tariff | (((dife >> DIFE_TARIFF_SHIFT) & 0x03) as u32) << (2 * index)
+        })
+    }
+
+    pub fn subunit(&self) -> u16 {
+        self.dife.iter().enumerate().fold(0, |subunit, (index, dife)| {
+            subunit | (((dife & DIFE_SUBUNIT) >> 6) as u16) << index
+        })
+    }
+
+    /// Get the quantity, unit and exponent of the value, or None if the VIF is not known.
+    /// The exponent includes the multiplicative correction factors of the combinable VIFEs.
+    pub fn value_information(&self) -> Option<ValueInformation> {
+        let mut value_information = match self.vif {
+            VIF_FIRST_EXTENSION => ValueInformation::from_first_extension(*self.vife.first()?),
+            VIF_SECOND_EXTENSION => ValueInformation::from_second_extension(*self.vife.first()?),
+            vif => ValueInformation::from_primary(vif),
+        }?;
+        for modifier in self.modifiers() {
+            if let VifModifier::MultiplicativeCorrection(exponent) = modifier {
+                value_information.exponent += exponent;
+            }
+        }
+        Some(value_information)
+    }
+
+    /// Get the modifiers of the combinable VIFEs, i.e. the VIFEs following the extension table VIF.
+    /// The VIFEs of a manufacturer specific VIF are not decoded.
+    pub fn modifiers(&self) -> Vec<VifModifier> {
+        match self.vif & !EXTENSION {
+            VIF_MANUFACTURER_SPECIFIC => Vec::new(),
+            _ if self.vif == VIF_FIRST_EXTENSION || self.vif == VIF_SECOND_EXTENSION => {
+                VifModifier::decode(self.vife.get(1..).unwrap_or(&[]))
+            }
+            _ => VifModifier::decode(&self.vife),
+        }
+    }
+
+    /// Get the unit of the plain text VIF in reading order.
+    pub fn plain_text_unit(&self) -> Option<String> {
+        let text = self.vif_text.as_ref()?;
+        String::from_utf8(text.iter().rev().copied().collect()).ok()
+    }
+
+    /// Decode the value, where dates and times are decoded by the data type implied by the VIF and the data field.
+    pub fn value(&self) -> Result<Value, ()> {
+        let is_date_time = self.is_date_time();
+        match self.data_field() {
+            DataField::Integer32 if self.is_daylight_saving() => {
+                Ok(Value::DaylightSaving(DaylightSaving::parse_type_k(&self.data)?))
+            }
+            DataField::Integer16 if is_date_time => Ok(Value::Date(Date::parse_type_g(&self.data)?)),
+            DataField::Integer24 if is_date_time => Ok(Value::Time(Time::parse_type_j(&self.data)?)),
+            DataField::Integer32 if is_date_time => Ok(Value::DateTime(DateTime::parse_type_f(&self.data)?)),
+            DataField::Integer48 if is_date_time => Ok(Value::DateTime(DateTime::parse_type_i(&self.data)?)),
+            DataField::VariableLength if is_date_time => Ok(Value::DateTime(DateTime::parse_type_m(&self.data)?)),
+            data_field => Value::decode(data_field, &self.data),
+        }
+    }
+
+    /// Get whether the value is a date or time according to the VIF.
+    fn is_date_time(&self) -> bool {
+        let quantity = self.value_information().map(|x| x.quantity);
+        matches!(
+            quantity,
+            Some(Quantity::Date | Quantity::DateTime | Quantity::TariffStart | Quantity::BatteryChangeDateTime)
+        ) || self
+            .modifiers()
+            .iter()
+            .any(|x| {
+                matches!(
+                    x,
+                    VifModifier::StartDateTime | VifModifier::LimitExceedDateTime { .. } | VifModifier::DateTime { .. }
+                )
+            })
+    }
+
+    fn is_daylight_saving(&self) -> bool {
+        self.value_information().map(|x| x.quantity) == Some(Quantity::DaylightSaving)
+    }
+
+    /// Get the value data, i.e. without the LVAR byte for variable length data.
+    pub fn value_data(&self) -> &[u8] {
+        match self.data_field() {
+            DataField::VariableLength => &self.data[1..],
+            _ => &self.data,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        let vif_text_size = self.vif_text.as_ref().map_or(0, |text| 1 + text.len());
+        1 + self.dife.len() + 1 + self.vife.len() + vif_text_size + self.data.len()
+    }
+
+    /// Get the typed content of the record, where manufacturer specific VIFEs are rejected.
+    pub fn typed(&self) -> Result<TypedRecord, ()> {
+        let modifiers = self.modifiers();
+        if modifiers.contains(&VifModifier::ManufacturerSpecific) {
+            return Err(());
+        }
+        Ok(TypedRecord {
+            value_information: self.value_information().ok_or(())?,
+            function: self.function(),
+            storage_number: self.storage_number(),
+            tariff: self.tariff(),
+            subunit: self.subunit(),
+            modifiers: modifiers
+                .into_iter()
+                .filter(|x| !matches!(x, VifModifier::MultiplicativeCorrection(_)))
+                .collect(),
+            value: self.value()?,
+        })
+    }
+
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        buf.push(self.dif);
+        buf.extend_from_slice(&self.dife);
+        buf.push(self.vif);
+        buf.extend_from_slice(&self.vife);
+        if let Some(text) = &self.vif_text {
+            buf.push(text.len() as u8);
+            buf.extend_from_slice(text);
+        }
+        buf.extend_from_slice(&self.data);
+    }
+}
+
+impl TypedRecord {
+    /// Encode the record with the fewest DIFEs and the smallest data field,
+    /// such that the typed content of the parsed record is the same.
+    pub fn encode(&self) -> Result<DataRecord, ()> {
+        let (vif, extension) = self.value_information.to_vif()?;
+        let mut record = DataRecord {
+            dif: (self.function as u8) << DIF_FUNCTION_SHIFT | ((self.storage_number & 1) as u8) << 6,
+            dife: Vec::new(),
+            vif,
+            vife: extension.into_iter().collect(),
+            vif_text: None,
+            data: Vec::new(),
+        };
+
+        // Each DIFE holds 4 bits of the storage number, 2 bits of the tariff and 1 bit of the subunit
+        let mut storage_number = self.storage_number >> 1;
+        let mut tariff = self.tariff;
+        let mut subunit = self.subunit;
+        while storage_number != 0 || tariff != 0 || subunit != 0 {
+            if record.dife.len() == MAX_EXTENSIONS {
+                return Err(());
+            }
+            match record.dife.last_mut() {
+                Some(dife) => *dife |= EXTENSION,
+                None => record.dif |= EXTENSION,
+            }
+            record.dife.push(
+                (storage_number & DIFE_STORAGE_NUMBER_MASK as u64) as u8
+                    | ((tariff & 0x03) as u8) << DIFE_TARIFF_SHIFT
+                    | ((subunit & 0x01) as u8) << 6,
+            );
+            storage_number >>= 4;
+            tariff >>= 2;
+            subunit >>= 1;
+        }
+
+        // The modifiers follow the VIF extension, with the extension bit set on the preceding byte
+        for vife in VifModifier::encode(&self.modifiers)? {
+            if record.vife.len() == MAX_EXTENSIONS {
+                return Err(());
+            }
+            match record.vife.last_mut() {
+                Some(last) => *last |= EXTENSION,
+                None => record.vif |= EXTENSION,
+            }
+            record.vife.push(vife);
+        }
+
+        // Values that would be decoded as another type with the VIF are rejected
+        let is_date_time = record.is_date_time();
+        let is_daylight_saving = record.is_daylight_saving();
+        match self.value {
+            Value::Integer(_) | Value::Binary(_) if is_date_time || is_daylight_saving => return Err(()),
+            Value::Date(_) | Value::Time(_) | Value::DateTime(_) if !is_date_time => return Err(()),
+            Value::DaylightSaving(_) if !is_daylight_saving => return Err(()),
+            _ => {}
+        }
+
+        let (data_field, data) = self.value.encode()?;
+        record.dif |= data_field as u8;
+        record.data = data;
+        Ok(record)
+    }
+}
+
+impl DataRecords {
+    /// Parse the records of the variable data structure, e.g. the application data following the transport layer header.
+    pub fn parse(data: &[u8]) -> Result<DataRecords, ()> {
+        let mut records = DataRecords::default();
+        let mut rest = data;
+        while let Some(&dif) = rest.first() {
+            match dif {
+                DIF_IDLE_FILLER => rest = &rest[1..],
+                DIF_MANUFACTURER_DATA | DIF_MORE_RECORDS => {
+                    records.manufacturer_data = Some(rest[1..].to_vec());
+                    records.more_records = dif == DIF_MORE_RECORDS;
+                    break;
+                }
+                DIF_GLOBAL_READOUT => {
+                    records.global_readout = true;
+                    rest = &rest[1..];
+                }
+                _ if dif & DIF_DATA_FIELD_MASK == DataField::SpecialFunctions as u8 => return Err(()),
+                _ => {
+                    let (record, size) = DataRecord::parse(rest)?;
+                    records.records.push(record);
+                    rest = &rest[size..];
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    /// Write the records followed by the global readout request and the manufacturer specific data.
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        for record in &self.records {
+            record.write(buf);
+        }
+        if self.global_readout {
+            buf.push(DIF_GLOBAL_READOUT);
+        }
+        if self.manufacturer_data.is_some() || self.more_records {
+            buf.push(if self.more_records { DIF_MORE_RECORDS } else { DIF_MANUFACTURER_DATA });
+        }
+        if let Some(data) = &self.manufacturer_data {
+            buf.extend_from_slice(data);
+        }
+    }
+}
+
+/// Get the size of variable length data from the LVAR byte.
+pub(crate) fn lvar_size(lvar: u8) -> Result<usize, ()> {
+    match lvar {
+        // Text
+        0x00..=0xBF => Ok(lvar as usize),
+        // Positive and negative BCD
+        0xC0..=0xC9 => Ok((lvar - 0xC0) as usize),
+        0xD0..=0xD9 => Ok((lvar - 0xD0) as usize),
+        // Binary
+        0xE0..=0xEF => Ok((lvar - 0xE0) as usize),
+        0xF0..=0xF4 => Ok(4 * (lvar - 0xEC) as usize),
+        0xF5 => Ok(48),
+        0xF6 => Ok(64),
+        _ => Err(()),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::vif::Unit;
+
+    use super::*;
+
+    #[test]
+    pub fn parse_records() {
+        let records = DataRecords::parse(&[
+            0x2F, 0x2F, // Idle filler
+            0x04, 0x13, 0x39, 0x30, 0x00, 0x00, // Volume
+            0x04, 0x6D, 0x32, 0x37, 0x1F, 0x15, // Date and time
+            0x02, 0xFD, 0x17, 0x00, 0x00, // Error flags
+            0x2F, 0x2F,
+        ])
+        .unwrap();
+        assert_eq!(3, records.records.len());
+        assert_eq!(None, records.manufacturer_data);
+
+        let date_time = match records.records[1].value().unwrap() {
+            Value::DateTime(date_time) => date_time,
+            _ => panic!(),
+        };
+        assert_eq!(Some(2008), date_time.date.year);
+        assert_eq!(Some(5), date_time.date.month);
+        assert_eq!(Some(31), date_time.date.day);
+        assert_eq!(Some(23), date_time.time.hour);
+        assert_eq!(Some(50), date_time.time.minute);
+
+        let volume = &records.records[0];
+        assert_eq!(DataField::Integer32, volume.data_field());
+        assert_eq!(Function::Instantaneous, volume.function());
+        assert_eq!(0x13, volume.vif);
+        assert_eq!(&[0x39, 0x30, 0x00, 0x00], volume.data.as_slice());
+        assert_eq!(6, volume.size());
+        assert_eq!(Ok(Value::Integer(12345)), volume.value());
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::Volume, Unit::CubicMetre, -3)),
+            volume.value_information()
+        );
+
+        let error_flags = &records.records[2];
+        assert_eq!(DataField::Integer16, error_flags.data_field());
+        assert_eq!(0xFD, error_flags.vif);
+        assert_eq!(&[0x17], error_flags.vife.as_slice());
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::ErrorFlags, Unit::None, 0)),
+            error_flags.value_information()
+        );
+    }
+
+    #[test]
+    pub fn parse_dife() {
+        let records = DataRecords::parse(&[0xCC, 0xF1, 0x02, 0x13, 0x78, 0x56, 0x34, 0x12]).unwrap();
+        let record = &records.records[0];
+        assert_eq!(DataField::Bcd8, record.data_field());
+        assert_eq!(Function::Instantaneous, record.function());
+        assert_eq!(1 | 1 << 1 | 2 << 5, record.storage_number());
+        assert_eq!(3, record.tariff());
+        assert_eq!(1, record.subunit());
+
+        let records = DataRecords::parse(&[0x14, 0x13, 0x39, 0x30, 0x00, 0x00]).unwrap();
+        assert_eq!(Function::Maximum, records.records[0].function());
+        assert_eq!(0, records.records[0].storage_number());
+    }
+
+    #[test]
+    pub fn parse_variable_length() {
+        let records = DataRecords::parse(&[0x0D, 0xFD, 0x11, 0x03, 0x43, 0x42, 0x41, 0x01, 0xFD, 0x0C, 0x07]).unwrap();
+        assert_eq!(2, records.records.len());
+        assert_eq!(DataField::VariableLength, records.records[0].data_field());
+        assert_eq!(&[0x43, 0x42, 0x41], records.records[0].value_data());
+        assert_eq!(7, records.records[0].size());
+    }
+
+    #[test]
+    pub fn parse_extension_vif() {
+        // The extension table VIF is missing
+        assert!(DataRecords::parse(&[0x01, 0xFD]).is_err());
+
+        let records = DataRecords::parse(&[0x01, 0xFB, 0x1A, 0x2D, 0x02, 0xFD, 0x46, 0xE8, 0x03]).unwrap();
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::RelativeHumidity, Unit::Percent, -1)),
+            records.records[0].value_information()
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::Voltage, Unit::Volt, -3)),
+            records.records[1].value_information()
+        );
+    }
+
+    #[test]
+    pub fn parse_combinable_vife() {
+        let records = DataRecords::parse(&[
+            0x04, 0xBB, 0x3C, 0x10, 0x27, 0x00, 0x00, // Volume flow of backward flow
+            0x02, 0x96, 0x73, 0xE8, 0x03, // Volume with correction factor
+            0x02, 0xFD, 0x97, 0x7E, 0x00, 0x00, // Future error flags
+            0x01, 0xFF, 0xA0, 0x01, 0x00, // Manufacturer specific
+            0x01, 0x93, 0xFF, 0x01, 0x00, // Volume with manufacturer specific VIFEs
+        ])
+        .unwrap();
+        let flow = &records.records[0];
+        assert_eq!(vec![VifModifier::NegativeAccumulation], flow.modifiers());
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::VolumeFlow, Unit::CubicMetrePerHour, -3)),
+            flow.value_information()
+        );
+
+        let volume = &records.records[1];
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::Volume, Unit::CubicMetre, -3)),
+            volume.value_information()
+        );
+
+        let error_flags = &records.records[2];
+        assert_eq!(vec![VifModifier::FutureValue], error_flags.modifiers());
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::ErrorFlags, Unit::None, 0)),
+            error_flags.value_information()
+        );
+        assert_eq!(vec![VifModifier::FutureValue], error_flags.typed().unwrap().modifiers);
+
+        assert!(records.records[3].modifiers().is_empty());
+        assert!(records.records[4].typed().is_err());
+
+        // The additive correction is kept as a modifier and is not applied to the value
+        let records = DataRecords::parse(&[0x02, 0x93, 0x7A, 0x64, 0x00]).unwrap();
+        let typed = records.records[0].typed().unwrap();
+        assert_eq!(ValueInformation::new(Quantity::Volume, Unit::CubicMetre, -3), typed.value_information);
+        assert_eq!(vec![VifModifier::AdditiveCorrection(-1)], typed.modifiers);
+        assert_eq!(Value::Integer(100), typed.value);
+    }
+
+    #[test]
+    pub fn parse_date_records() {
+        let records = DataRecords::parse(&[
+            0x42, 0x6C, 0xBF, 0x2C, // Due date
+            0x84, 0x10, 0x13, 0xCE, 0x01, 0x00, 0x00, // Volume in tariff 1
+            0x04, 0xFD, 0xF2, 0x00, 0x22, 0x3F, 0xA3, 0x1B, // Daylight saving
+            0x02, 0xDA, 0x39, 0xBF, 0x2C, // Start date of the flow temperature
+            0x02, 0xDA, 0x6F, 0xBF, 0x2C, // End date of the last flow temperature
+        ])
+        .unwrap();
+        let due_date = Date {
+            year: Some(2021),
+            month: Some(12),
+            day: Some(31),
+        };
+        assert_eq!(Ok(Value::Date(due_date)), records.records[0].value());
+        assert_eq!(1, records.records[0].storage_number());
+        assert_eq!(Ok(Value::Integer(462)), records.records[1].value());
+        assert!(matches!(records.records[2].value(), Ok(Value::DaylightSaving(_))));
+        assert_eq!(Ok(Value::Date(due_date)), records.records[3].value());
+        assert_eq!(Ok(Value::Date(due_date)), records.records[4].value());
+    }
+
+    fn typed_record(value_information: ValueInformation, value: Value) -> TypedRecord {
+        TypedRecord {
+            value_information,
+            function: Function::Instantaneous,
+            storage_number: 0,
+            tariff: 0,
+            subunit: 0,
+            modifiers: Vec::new(),
+            value,
+        }
+    }
+
+    #[test]
+    pub fn encode_typed_record() {
+        let volume = ValueInformation::new(Quantity::Volume, Unit::CubicMetre, -3);
+        let mut buf = Vec::new();
+        typed_record(volume, Value::Integer(12345)).encode().unwrap().write(&mut buf);
+        assert_eq!(&[0x02, 0x13, 0x39, 0x30], buf.as_slice());
+
+        let record = TypedRecord {
+            function: Function::Maximum,
+            storage_number: 3,
+            tariff: 3,
+            subunit: 1,
+            ..typed_record(volume, Value::Bcd(12345678))
+        };
+        let mut buf = Vec::new();
+        record.encode().unwrap().write(&mut buf);
+        assert_eq!(&[0xDC, 0x71, 0x13, 0x78, 0x56, 0x34, 0x12], buf.as_slice());
+
+        let error_flags = ValueInformation::new(Quantity::ErrorFlags, Unit::None, 0);
+        let mut buf = Vec::new();
+        typed_record(error_flags, Value::Integer(0x0100)).encode().unwrap().write(&mut buf);
+        assert_eq!(&[0x02, 0xFD, 0x17, 0x00, 0x01], buf.as_slice());
+
+        let record = TypedRecord {
+            modifiers: vec![VifModifier::PerTime(Unit::Hour), VifModifier::Extension(0x01)],
+            ..typed_record(volume, Value::Integer(12345))
+        };
+        let mut buf = Vec::new();
+        record.encode().unwrap().write(&mut buf);
+        assert_eq!(&[0x02, 0x93, 0xA2, 0xFC, 0x01, 0x39, 0x30], buf.as_slice());
+    }
+
+    #[test]
+    pub fn encode_round_trip() {
+        let date = Date {
+            year: Some(2021),
+            month: Some(12),
+            day: Some(31),
+        };
+        let date_time = DateTime {
+            date,
+            time: Time {
+                hour: Some(23),
+                minute: Some(50),
+                second: None,
+            },
+            invalid: false,
+            summer_time: true,
+            day_of_week: None,
+            week: None,
+        };
+        let records = [
+            typed_record(ValueInformation::new(Quantity::Energy, Unit::WattHour, 6), Value::Integer(-1)),
+            typed_record(ValueInformation::new(Quantity::Date, Unit::None, 0), Value::Date(date)),
+            typed_record(ValueInformation::new(Quantity::DateTime, Unit::None, 0), Value::DateTime(date_time)),
+            typed_record(
+                ValueInformation::new(Quantity::DateTime, Unit::None, 0),
+                Value::DateTime(DateTime {
+                    week: Some(52),
+                    ..date_time
+                }),
+            ),
+            typed_record(
+                ValueInformation::new(Quantity::Customer, Unit::None, 0),
+                Value::Text("Customer".into()),
+            ),
+            typed_record(ValueInformation::new(Quantity::Volume, Unit::CubicFoot, -1), Value::Real(1.5)),
+            TypedRecord {
+                modifiers: vec![
+                    VifModifier::PerTime(Unit::Hour),
+                    VifModifier::LimitValue { upper: true },
+                    VifModifier::AdditiveCorrection(-1),
+                    VifModifier::Extension(0x01),
+                    VifModifier::FutureValue,
+                ],
+                ..typed_record(ValueInformation::new(Quantity::Volume, Unit::CubicMetre, -3), Value::Integer(2))
+            },
+            TypedRecord {
+                modifiers: vec![VifModifier::DateTime { last: true, end: true }],
+                ..typed_record(ValueInformation::new(Quantity::FlowTemperature, Unit::Celsius, -1), Value::Date(date))
+            },
+            TypedRecord {
+                storage_number: 0x1FF_FFFF_FFFF,
+                tariff: 0xF_FFFF,
+                subunit: 0x3FF,
+                function: Function::DuringError,
+                ..typed_record(ValueInformation::new(Quantity::Pressure, Unit::Bar, -2), Value::None)
+            },
+        ];
+        for typed in records.iter() {
+            let mut buf = Vec::new();
+            typed.encode().unwrap().write(&mut buf);
+            let parsed = DataRecords::parse(&buf).unwrap();
+            assert_eq!(1, parsed.records.len());
+            assert_eq!(buf.len(), parsed.records[0].size());
+            assert_eq!(Ok(typed.clone()), parsed.records[0].typed());
+        }
+    }
+
+    #[test]
+    pub fn encode_invalid_typed_record() {
+        let date = ValueInformation::new(Quantity::Date, Unit::None, 0);
+        let volume = ValueInformation::new(Quantity::Volume, Unit::CubicMetre, -3);
+        // The integer would be decoded as a date
+        assert!(typed_record(date, Value::Integer(1)).encode().is_err());
+        // The date would be decoded as an integer
+        let value = Value::Date(Date {
+            year: None,
+            month: None,
+            day: None,
+        });
+        assert!(typed_record(volume, value).encode().is_err());
+        // The storage number exceeds the DIFEs
+        let record = TypedRecord {
+            storage_number: 1 << 41,
+            ..typed_record(volume, Value::None)
+        };
+        assert!(record.encode().is_err());
+        // The modifier is not a combinable VIFE
+        let record = TypedRecord {
+            modifiers: vec![VifModifier::Reserved(0x7C)],
+            ..typed_record(volume, Value::None)
+        };
+        assert!(record.encode().is_err());
+        // The exponent is not in the VIF table
+        let record = typed_record(ValueInformation::new(Quantity::Volume, Unit::CubicMetre, 5), Value::None);
+        assert!(record.encode().is_err());
+    }
+
+    #[test]
+    pub fn write_records() {
+        let data = [
+            0x04, 0x13, 0x39, 0x30, 0x00, 0x00, 0x02, 0x7C, 0x03, 0x4D, 0x50, 0x52, 0xE8, 0x03, 0x7F, 0x1F, 0x01, 0x02,
+        ];
+        let records = DataRecords::parse(&data).unwrap();
+        let mut buf = Vec::new();
+        records.write(&mut buf);
+        assert_eq!(&data, buf.as_slice());
+    }
+
+    #[test]
+    pub fn parse_plain_text_vif() {
+        let records = DataRecords::parse(&[0x02, 0x7C, 0x03, 0x4D, 0x50, 0x52, 0xE8, 0x03, 0x01, 0x13, 0x05]).unwrap();
+        let record = &records.records[0];
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::PlainText, Unit::None, 0)),
+            record.value_information()
+        );
+        assert_eq!(Some("RPM"), record.plain_text_unit().as_deref());
+        assert_eq!(&[0xE8, 0x03], record.data.as_slice());
+        assert_eq!(8, record.size());
+        assert_eq!(2, records.records.len());
+    }
+
+    #[test]
+    pub fn parse_manufacturer_data() {
+        let records = DataRecords::parse(&[0x01, 0x13, 0x05, 0x1F, 0x01, 0x02, 0x2F]).unwrap();
+        assert_eq!(1, records.records.len());
+        assert_eq!(Some(vec![0x01, 0x02, 0x2F]), records.manufacturer_data);
+        assert!(records.more_records);
+
+        let records = DataRecords::parse(&[0x0F]).unwrap();
+        assert_eq!(Some(vec![]), records.manufacturer_data);
+        assert!(!records.more_records);
+
+        let records = DataRecords::parse(&[0x7F]).unwrap();
+        assert!(records.global_readout);
+    }
+
+    #[test]
+    pub fn parse_invalid() {
+        // Truncated data
+        assert!(DataRecords::parse(&[0x04, 0x13, 0x39, 0x30]).is_err());
+        // Missing VIF
+        assert!(DataRecords::parse(&[0x84, 0x01]).is_err());
+        // Reserved special function
+        assert!(DataRecords::parse(&[0x3F]).is_err());
+        // Reserved LVAR
+        assert!(DataRecords::parse(&[0x0D, 0xFD, 0x11, 0xF8]).is_err());
+        // Too many DIFE
+        assert!(DataRecords::parse(&[0x84, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00, 0x13]).is_err());
+    }
+}
@@ -0,0 +1,466 @@
+use core::convert::TryInto;
+
+const UNSPECIFIED_YEAR: u8 = 127;
+const UNSPECIFIED_MONTH: u8 = 15;
+const UNSPECIFIED_DAY: u8 = 0;
+const UNSPECIFIED_HOUR: u8 = 31;
+const UNSPECIFIED_MINUTE: u8 = 63;
+const UNSPECIFIED_SECOND: u8 = 63;
+
+/// A date according to EN13757-3, where None fields are unspecified, e.g. every year for a due date.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Date {
+    pub year: Option<u16>,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+}
+
+/// A time according to EN13757-3, where None fields are unspecified.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Time {
+    pub hour: Option<u8>,
+    pub minute: Option<u8>,
+    pub second: Option<u8>,
+}
+
+/// A date and time according to EN13757-3.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DateTime {
+    pub date: Date,
+    pub time: Time,
+    /// The time is invalid, e.g. the clock is not set.
+    pub invalid: bool,
+    pub summer_time: bool,
+    /// The day of week from 1 (Monday) to 7 for type I.
+    pub day_of_week: Option<u8>,
+    /// The week of year from 1 to 53 for type I.
+    pub week: Option<u8>,
+}
+
+/// The daylight saving descriptor of data type K.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DaylightSaving {
+    pub begin_hour: u8,
+    pub begin_day: u8,
+    pub begin_month: u8,
+    pub end_day: u8,
+    pub end_month: u8,
+    /// The deviation in hours of the daylight saving time from the local standard time.
+    pub deviation: i8,
+    /// The deviation in hours of the local standard time from UTC.
+    pub local_deviation: i8,
+}
+
+impl Date {
+    /// Parse the 16 bit date of type G, i.e. the day and year bits 0-2 in the first byte,
+    /// and the month and year bits 3-6 in the second byte.
+    pub fn parse_type_g(data: &[u8]) -> Result<Date, ()> {
+        if data.len() != 2 {
+            return Err(());
+        }
+        Self::parse_fields(data[0], data[1], 0)
+    }
+
+    pub fn to_type_g(&self) -> Result<[u8; 2], ()> {
+        let (low, high, _) = self.fields_without_century()?;
+        Ok([low, high])
+    }
+
+    /// Parse the day, month and year fields, where the year may be extended with the hundred year field.
+    fn parse_fields(low: u8, high: u8, century: u8) -> Result<Date, ()> {
+        let day = low & 0x1F;
+        let month = high & 0x0F;
+        let year = (low >> 5) | (high & 0xF0) >> 1;
+        Ok(Date {
+            year: match year {
+                UNSPECIFIED_YEAR => None,
+                // Years before 81 are in the 21st century when the hundred year field is not used
+                0..=80 if century == 0 => Some(2000 + year as u16),
+                0..=99 => Some(1900 + 100 * century as u16 + year as u16),
+                _ => return Err(()),
+            },
+            month: match month {
+                UNSPECIFIED_MONTH => None,
+                1..=12 => Some(month),
+                _ => return Err(()),
+            },
+            day: match day {
+                UNSPECIFIED_DAY => None,
+                day => Some(day),
+            },
+        })
+    }
+
+    /// Get the day and month bytes for a date without the hundred year field, i.e. from 1981 to 2080.
+    fn fields_without_century(&self) -> Result<(u8, u8, u8), ()> {
+        match self.year {
+            Some(year) if !(1981..=2080).contains(&year) => Err(()),
+            _ => self.fields(),
+        }
+    }
+
+    /// Get the day and month bytes together with the hundred year field.
+    fn fields(&self) -> Result<(u8, u8, u8), ()> {
+        let (year, century) = match self.year {
+            None => (UNSPECIFIED_YEAR, 0),
+            Some(year @ 1981..=1999) => ((year - 1900) as u8, 0),
+            Some(year @ 2000..=2299) => ((year % 100) as u8, ((year - 1900) / 100) as u8),
+            Some(_) => return Err(()),
+        };
+        let month = match self.month {
+            None => UNSPECIFIED_MONTH,
+            Some(month @ 1..=12) => month,
+            Some(_) => return Err(()),
+        };
+        let day = match self.day {
+            None => UNSPECIFIED_DAY,
+            Some(day @ 1..=31) => day,
+            Some(_) => return Err(()),
+        };
+        Ok((day | (year & 0x07) << 5, month | (year & 0x78) << 1, century))
+    }
+}
+
+impl Time {
+    /// Parse the 24 bit time of type J, i.e. the second, minute and hour bytes.
+    pub fn parse_type_j(data: &[u8]) -> Result<Time, ()> {
+        if data.len() != 3 {
+            return Err(());
+        }
+        Self::parse_fields(data[0] & 0x3F, data[1] & 0x3F, data[2] & 0x1F)
+    }
+
+    pub fn to_type_j(&self) -> Result<[u8; 3], ()> {
+        Ok([self.second_field()?, self.minute_field()?, self.hour_field()?])
+    }
+
+    fn parse_fields(second: u8, minute: u8, hour: u8) -> Result<Time, ()> {
+        Ok(Time {
+            hour: match hour {
+                UNSPECIFIED_HOUR => None,
+                0..=23 => Some(hour),
+                _ => return Err(()),
+            },
+            minute: match minute {
+                UNSPECIFIED_MINUTE => None,
+                0..=59 => Some(minute),
+                _ => return Err(()),
+            },
+            second: match second {
+                UNSPECIFIED_SECOND => None,
+                0..=59 => Some(second),
+                _ => return Err(()),
+            },
+        })
+    }
+
+    fn hour_field(&self) -> Result<u8, ()> {
+        match self.hour {
+            None => Ok(UNSPECIFIED_HOUR),
+            Some(hour @ 0..=23) => Ok(hour),
+            Some(_) => Err(()),
+        }
+    }
+
+    fn minute_field(&self) -> Result<u8, ()> {
+        match self.minute {
+            None => Ok(UNSPECIFIED_MINUTE),
+            Some(minute @ 0..=59) => Ok(minute),
+            Some(_) => Err(()),
+        }
+    }
+
+    fn second_field(&self) -> Result<u8, ()> {
+        match self.second {
+            None => Ok(UNSPECIFIED_SECOND),
+            Some(second @ 0..=59) => Ok(second),
+            Some(_) => Err(()),
+        }
+    }
+}
+
+impl DateTime {
+    /// Parse the 32 bit date and time of type F, i.e. the minute and invalid bit,
+    /// the hour, hundred year and summer time bits, followed by the date of type G.
+    pub fn parse_type_f(data: &[u8]) -> Result<DateTime, ()> {
+        if data.len() != 4 {
+            return Err(());
+        }
+        Ok(DateTime {
+            date: Date::parse_fields(data[2], data[3], (data[1] >> 5) & 0x03)?,
+            time: Time {
+                second: None,
+                ..Time::parse_fields(0, data[0] & 0x3F, data[1] & 0x1F)?
+            },
+            invalid: data[0] & 0x80 != 0,
+            summer_time: data[1] & 0x80 != 0,
+            day_of_week: None,
+            week: None,
+        })
+    }
+
+    /// Get the date and time of type F, where the seconds are truncated.
+    pub fn to_type_f(&self) -> Result<[u8; 4], ()> {
+        let (low, high, century) = self.date.fields()?;
+        Ok([
+            self.time.minute_field()? | (self.invalid as u8) << 7,
+            self.time.hour_field()? | century << 5 | (self.summer_time as u8) << 7,
+            low,
+            high,
+        ])
+    }
+
+    /// Parse the 48 bit date and time of type I, i.e. the second and leap year bit,
+    /// the minute, summer time and invalid bits, the hour and day of week,
+    /// the date of type G and the week.
+    pub fn parse_type_i(data: &[u8]) -> Result<DateTime, ()> {
+        if data.len() != 6 {
+            return Err(());
+        }
+        let day_of_week = data[2] >> 5;
+        let week = data[5] & 0x3F;
+        Ok(DateTime {
+            date: Date::parse_fields(data[3], data[4], 0)?,
+            time: Time::parse_fields(data[0] & 0x3F, data[1] & 0x3F, data[2] & 0x1F)?,
+            invalid: data[1] & 0x80 != 0,
+            summer_time: data[1] & 0x40 != 0,
+            day_of_week: match day_of_week {
+                0 => None,
+                day_of_week => Some(day_of_week),
+            },
+            week: match week {
+                0 => None,
+                1..=53 => Some(week),
+                _ => return Err(()),
+            },
+        })
+    }
+
+    pub fn to_type_i(&self) -> Result<[u8; 6], ()> {
+        let (low, high, _) = self.date.fields_without_century()?;
+        let leap_year = matches!(self.date.year, Some(year) if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0));
+        let day_of_week = match self.day_of_week {
+            None => 0,
+            Some(day_of_week @ 1..=7) => day_of_week,
+            Some(_) => return Err(()),
+        };
+        let week = match self.week {
+            None => 0,
+            Some(week @ 1..=53) => week,
+            Some(_) => return Err(()),
+        };
+        Ok([
+            self.time.second_field()? | (leap_year as u8) << 7,
+            self.time.minute_field()? | (self.summer_time as u8) << 6 | (self.invalid as u8) << 7,
+            self.time.hour_field()? | day_of_week << 5,
+            low,
+            high,
+            week,
+        ])
+    }
+
+    /// Parse the variable length date and time of type M starting with the LVAR byte,
+    /// where the size of the binary number selects the type F or I format.
+    pub fn parse_type_m(data: &[u8]) -> Result<DateTime, ()> {
+        match data.split_first() {
+            Some((0xE4, data)) => Self::parse_type_f(data),
+            Some((0xE6, data)) => Self::parse_type_i(data),
+            _ => Err(()),
+        }
+    }
+
+    /// Get the date and time of type M in the type I format, starting with the LVAR byte.
+    pub fn to_type_m(&self) -> Result<[u8; 7], ()> {
+        let mut data = [0xE6; 7];
+        data[1..].copy_from_slice(&self.to_type_i()?);
+        Ok(data)
+    }
+}
+
+impl DaylightSaving {
+    /// Parse the 32 bit daylight saving descriptor of type K, i.e. the begin hour and deviation,
+    /// the begin day and local deviation bits 0-2, the begin and end months,
+    /// and the end day and local deviation bits 3-5.
+    pub fn parse_type_k(data: &[u8]) -> Result<DaylightSaving, ()> {
+        let data: [u8; 4] = data.try_into().map_err(|_| ())?;
+        let local_deviation = (data[1] >> 5) | (data[3] & 0xE0) >> 2;
+        let descriptor = Self {
+            begin_hour: data[0] & 0x1F,
+            begin_day: data[1] & 0x1F,
+            begin_month: data[2] & 0x0F,
+            end_day: data[3] & 0x1F,
+            end_month: data[2] >> 4,
+            // Sign extend the two and six bit fields
+            deviation: ((data[0] & 0x60) << 1) as i8 >> 6,
+            local_deviation: (local_deviation << 2) as i8 >> 2,
+        };
+        if !descriptor.is_valid() {
+            return Err(());
+        }
+        Ok(descriptor)
+    }
+
+    pub fn to_type_k(&self) -> Result<[u8; 4], ()> {
+        if !self.is_valid() {
+            return Err(());
+        }
+        let deviation = self.deviation as u8 & 0x03;
+        let local_deviation = self.local_deviation as u8 & 0x3F;
+        Ok([
+            self.begin_hour | deviation << 5,
+            self.begin_day | (local_deviation & 0x07) << 5,
+            self.begin_month | self.end_month << 4,
+            self.end_day | (local_deviation & 0x38) << 2,
+        ])
+    }
+
+    fn is_valid(&self) -> bool {
+        self.begin_hour <= 23
+            && (1..=31).contains(&self.begin_day)
+            && (1..=12).contains(&self.begin_month)
+            && (1..=31).contains(&self.end_day)
+            && (1..=12).contains(&self.end_month)
+            && (-2..=1).contains(&self.deviation)
+            && (-32..=31).contains(&self.local_deviation)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn date(year: u16, month: u8, day: u8) -> Date {
+        Date {
+            year: Some(year),
+            month: Some(month),
+            day: Some(day),
+        }
+    }
+
+    fn time(hour: u8, minute: u8, second: Option<u8>) -> Time {
+        Time {
+            hour: Some(hour),
+            minute: Some(minute),
+            second,
+        }
+    }
+
+    #[test]
+    pub fn type_g() {
+        // 2021-12-31
+        assert_eq!(Ok(date(2021, 12, 31)), Date::parse_type_g(&[0xBF, 0x2C]));
+        assert_eq!(Ok([0xBF, 0x2C]), date(2021, 12, 31).to_type_g());
+        assert_eq!(Ok(date(1999, 1, 1)), Date::parse_type_g(&[0x61, 0xC1]));
+        assert_eq!(Ok([0x61, 0xC1]), date(1999, 1, 1).to_type_g());
+
+        // Every year on the 1st of June
+        let due_date = Date {
+            year: None,
+            month: Some(6),
+            day: Some(1),
+        };
+        assert_eq!(Ok(due_date), Date::parse_type_g(&[0xE1, 0xF6]));
+        assert_eq!(Ok([0xE1, 0xF6]), due_date.to_type_g());
+
+        // Invalid month and year
+        assert!(Date::parse_type_g(&[0x01, 0x0D]).is_err());
+        assert!(Date::parse_type_g(&[0x81, 0xC1]).is_err());
+        assert!(date(2081, 1, 1).to_type_g().is_err());
+    }
+
+    #[test]
+    pub fn type_f() {
+        // 2021-05-31 23:50
+        let data = [0x32, 0x37, 0xBF, 0x25];
+        let date_time = DateTime {
+            date: date(2021, 5, 31),
+            time: time(23, 50, None),
+            invalid: false,
+            summer_time: false,
+            day_of_week: None,
+            week: None,
+        };
+        assert_eq!(Ok(date_time), DateTime::parse_type_f(&data));
+        assert_eq!(Ok(data), date_time.to_type_f());
+
+        // The hundred year field extends the year
+        let date_time = DateTime {
+            date: date(2121, 5, 31),
+            invalid: true,
+            summer_time: true,
+            ..date_time
+        };
+        assert_eq!(Ok([0xB2, 0xD7, 0xBF, 0x25]), date_time.to_type_f());
+        assert_eq!(Ok(date_time), DateTime::parse_type_f(&[0xB2, 0xD7, 0xBF, 0x25]));
+
+        assert!(DateTime::parse_type_f(&[0x3C, 0x17, 0x3F, 0x25]).is_err());
+    }
+
+    #[test]
+    pub fn type_i() {
+        // Monday 2024-02-26 13:45:30 in week 9
+        let date_time = DateTime {
+            date: date(2024, 2, 26),
+            time: time(13, 45, Some(30)),
+            invalid: false,
+            summer_time: false,
+            day_of_week: Some(1),
+            week: Some(9),
+        };
+        let data = [0x9E, 0x2D, 0x2D, 0x1A, 0x32, 0x09];
+        assert_eq!(Ok(data), date_time.to_type_i());
+        assert_eq!(Ok(date_time), DateTime::parse_type_i(&data));
+
+        let mut m = [0xE6; 7];
+        m[1..].copy_from_slice(&data);
+        assert_eq!(Ok(m), date_time.to_type_m());
+        assert_eq!(Ok(date_time), DateTime::parse_type_m(&m));
+        assert!(DateTime::parse_type_m(&m[..6]).is_err());
+
+        // Type I has no hundred year field
+        let date_time = DateTime {
+            date: date(2121, 5, 31),
+            ..date_time
+        };
+        assert!(date_time.to_type_i().is_err());
+    }
+
+    #[test]
+    pub fn type_j() {
+        assert_eq!(Ok(time(8, 30, Some(15))), Time::parse_type_j(&[0x0F, 0x1E, 0x08]));
+        assert_eq!(Ok([0x0F, 0x1E, 0x08]), time(8, 30, Some(15)).to_type_j());
+
+        let every_hour = Time {
+            hour: None,
+            minute: Some(0),
+            second: Some(0),
+        };
+        assert_eq!(Ok(every_hour), Time::parse_type_j(&[0x00, 0x00, 0x1F]));
+        assert!(Time::parse_type_j(&[0x3C, 0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    pub fn type_k() {
+        // From the last Sunday of March at 2:00 to October, one hour ahead of UTC+1
+        let descriptor = DaylightSaving {
+            begin_hour: 2,
+            begin_day: 31,
+            begin_month: 3,
+            end_day: 27,
+            end_month: 10,
+            deviation: 1,
+            local_deviation: 1,
+        };
+        let data = descriptor.to_type_k().unwrap();
+        assert_eq!([0x22, 0x3F, 0xA3, 0x1B], data);
+        assert_eq!(Ok(descriptor), DaylightSaving::parse_type_k(&data));
+
+        let descriptor = DaylightSaving {
+            deviation: -1,
+            local_deviation: -5,
+            ..descriptor
+        };
+        assert_eq!(Ok(descriptor), DaylightSaving::parse_type_k(&descriptor.to_type_k().unwrap()));
+        assert!(DaylightSaving::parse_type_k(&[0x22, 0x3F, 0xA0, 0x1B]).is_err());
+    }
+}
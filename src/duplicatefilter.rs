@@ -0,0 +1,158 @@
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+
+use crate::{mbusaddress::MBusAddress, wmbus::WMBusPacket};
+
+const HASH: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+#[derive(Clone, Copy)]
+struct Entry {
+    address: MBusAddress,
+    control: u8,
+    acc: Option<u8>,
+    hash: u32,
+    received_at: Duration,
+}
+
+/// Filter for telegrams transmitted more than once by the meter or echoed by repeaters.
+/// The filter remembers the latest N unique telegrams.
+pub struct DuplicateFilter<const N: usize> {
+    entries: [Option<Entry>; N],
+    next_index: usize,
+    window: Duration,
+}
+
+impl<const N: usize> DuplicateFilter<N> {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            entries: [None; N],
+            next_index: 0,
+            window,
+        }
+    }
+
+    /// Check whether the packet is a duplicate of a telegram received within the window,
+    /// and remember the packet if it is not.
+    pub fn is_duplicate(&mut self, packet: &WMBusPacket, received_at: Duration) -> bool {
+        let entry = Entry {
+            address: packet.link_layer.address,
+            control: packet.link_layer.control,
+            acc: access_number(packet),
+            hash: payload_hash(packet),
+            received_at,
+        };
+
+        let duplicate = self.entries.iter().flatten().any(|existing| {
+            received_at.saturating_sub(existing.received_at) <= self.window
+                && existing.address == entry.address
+                && existing.control == entry.control
+                && existing.acc == entry.acc
+                && existing.hash == entry.hash
+        });
+
+        if !duplicate && N > 0 {
+            self.entries[self.next_index] = Some(entry);
+            self.next_index = (self.next_index + 1) % N;
+        }
+
+        duplicate
+    }
+}
+
+fn access_number(packet: &WMBusPacket) -> Option<u8> {
+    if let Some(ell) = &packet.ext_link_layer {
+        return Some(ell.acc());
+    }
+
+    packet.transport_layer().ok().flatten().map(|tpl| tpl.acc())
+}
+
+/// Hash the authentication and application layers, the extended link layer is excluded as it is changed by repeaters.
+fn payload_hash(packet: &WMBusPacket) -> u32 {
+    let mut digest = HASH.digest();
+    if let Some(afl) = &packet.authentication_layer {
+        let mut afl_bytes = Vec::new();
+        afl.write(&mut afl_bytes);
+        digest.update(&afl_bytes);
+    }
+    if let Some(apl) = &packet.application_layer {
+        digest.update(&[apl.ci]);
+        digest.update(&apl.data);
+    }
+    digest.finalize()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        mbusaddress::{DeviceType, ManufacturerCode},
+        wmbus::{ApplicationLayer, CommunicationControl, ExtendedLinkLayer, LinkLayer},
+    };
+
+    use super::*;
+
+    fn packet(serial_number: u32, acc: u8, data: &[u8]) -> WMBusPacket {
+        WMBusPacket {
+            application_layer: Some(ApplicationLayer {
+                ci: 0x7A,
+                data: [&[acc, 0x00, 0x00, 0x00], data].concat(),
+            }),
+            authentication_layer: None,
+            ext_link_layer: None,
+            link_layer: LinkLayer {
+                length: None,
+                control: 0x44,
+                address: MBusAddress::new(ManufacturerCode::KAM, serial_number, 0x01, DeviceType::Water),
+            },
+        }
+    }
+
+    #[test]
+    pub fn detects_duplicates_within_window() {
+        let mut filter = DuplicateFilter::<4>::new(Duration::from_secs(5));
+
+        assert!(!filter.is_duplicate(&packet(1, 0x10, &[0x01]), Duration::from_secs(100)));
+        assert!(filter.is_duplicate(&packet(1, 0x10, &[0x01]), Duration::from_secs(101)));
+        assert!(filter.is_duplicate(&packet(1, 0x10, &[0x01]), Duration::from_secs(105)));
+        assert!(!filter.is_duplicate(&packet(1, 0x10, &[0x01]), Duration::from_secs(106)));
+    }
+
+    #[test]
+    pub fn distinguishes_address_acc_and_payload() {
+        let mut filter = DuplicateFilter::<4>::new(Duration::from_secs(5));
+
+        assert!(!filter.is_duplicate(&packet(1, 0x10, &[0x01]), Duration::ZERO));
+        assert!(!filter.is_duplicate(&packet(2, 0x10, &[0x01]), Duration::ZERO));
+        assert!(!filter.is_duplicate(&packet(1, 0x11, &[0x01]), Duration::ZERO));
+        assert!(!filter.is_duplicate(&packet(1, 0x10, &[0x02]), Duration::ZERO));
+        assert!(filter.is_duplicate(&packet(2, 0x10, &[0x01]), Duration::ZERO));
+    }
+
+    #[test]
+    pub fn detects_repeated_duplicates() {
+        let mut filter = DuplicateFilter::<4>::new(Duration::from_secs(5));
+        let mut original = packet(1, 0x00, &[0x01]);
+        original.ext_link_layer = Some(ExtendedLinkLayer::Short { cc: 0x00, acc: 0x10 });
+        let mut repeated = original.clone();
+        repeated.ext_link_layer = Some(ExtendedLinkLayer::Short {
+            cc: CommunicationControl::HOP_COUNT,
+            acc: 0x10,
+        });
+
+        assert!(!filter.is_duplicate(&original, Duration::ZERO));
+        assert!(filter.is_duplicate(&repeated, Duration::from_secs(1)));
+    }
+
+    #[test]
+    pub fn forgets_oldest_when_full() {
+        let mut filter = DuplicateFilter::<2>::new(Duration::from_secs(5));
+
+        assert!(!filter.is_duplicate(&packet(1, 0x10, &[]), Duration::ZERO));
+        assert!(!filter.is_duplicate(&packet(2, 0x10, &[]), Duration::ZERO));
+        assert!(!filter.is_duplicate(&packet(3, 0x10, &[]), Duration::ZERO));
+        assert!(!filter.is_duplicate(&packet(1, 0x10, &[]), Duration::ZERO));
+        assert!(filter.is_duplicate(&packet(3, 0x10, &[]), Duration::ZERO));
+    }
+}
@@ -24,9 +24,14 @@ const MIN_PAYLOAD_SIZE: usize = FIRST_BLOCK_PAYLOAD_SIZE + 1;
 const MAX_PAYLOAD_SIZE: usize = 256;
 const MAX_FRAME_SIZE: usize = MAX_PAYLOAD_SIZE + 2 * BLOCK_MAX_PAYLOAD_SIZES.len();
 
+#[derive(Clone, Copy)]
 pub struct FrameFormatA;
 
 impl FrameFormat for FrameFormatA {
+    fn length_includes_crc() -> bool {
+        false
+    }
+
     fn block_has_crc(_block_index: usize) -> bool {
         true
     }
@@ -10,9 +10,14 @@ const MIN_PAYLOAD_SIZE: usize = FIRST_BLOCK_PAYLOAD_SIZE + 1;
 const MAX_PAYLOAD_SIZE: usize = 256;
 const MAX_FRAME_SIZE: usize = MAX_PAYLOAD_SIZE + 2 * 2;
 
+#[derive(Clone, Copy)]
 pub struct FrameFormatB;
 
 impl FrameFormat for FrameFormatB {
+    fn length_includes_crc() -> bool {
+        true
+    }
+
     fn block_has_crc(block_index: usize) -> bool {
         block_index > 0
     }
@@ -1,6 +1,8 @@
 use core::{cmp::min, marker::PhantomData};
 
 pub trait FrameFormat: Sized {
+    /// Get whether the CRC fields are included in the L-field.
+    fn length_includes_crc() -> bool;
     fn block_has_crc(block_index: usize) -> bool;
     fn block_max_payload_size(block_index: usize) -> usize;
     fn block_max_frame_size(block_index: usize) -> usize {
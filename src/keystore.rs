@@ -0,0 +1,179 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::{crypto::Key, mbusaddress::MBusAddress};
+
+/// A store of the keys used for decryption, where the keys are the key handles of the crypto backend.
+pub trait KeyStore<K = Key> {
+    /// Get the candidate keys for the meter or radio adapter with the address, in the order they should be tried.
+    fn keys(&self, address: &MBusAddress) -> &[K];
+}
+
+/// The index of the candidate key that decrypted each layer, or None if the layer was not encrypted.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UsedKeys {
+    pub ell: Option<usize>,
+    pub tpl: Option<usize>,
+}
+
+/// An in-memory key store where the keys are looked up by manufacturer and serial number.
+pub struct MemoryKeyStore<K = Key> {
+    keys: BTreeMap<(u16, u32), Vec<K>>,
+}
+
+impl<K> MemoryKeyStore<K> {
+    pub fn new() -> Self {
+        Self { keys: BTreeMap::new() }
+    }
+
+    /// Add a candidate key for the meter with the address, after any keys already added.
+    pub fn add(&mut self, address: &MBusAddress, key: K) {
+        self.add_by_serial_number(address.manufacturer_code, address.serial_number.bcd_value(), key);
+    }
+
+    fn add_by_serial_number(&mut self, manufacturer_code: u16, serial_number_bcd: u32, key: K) {
+        self.keys
+            .entry((manufacturer_code, serial_number_bcd))
+            .or_default()
+            .push(key);
+    }
+}
+
+impl<K> Default for MemoryKeyStore<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryKeyStore<Key> {
+    /// Parse a key file where each line holds the manufacturer, the serial number and the hex encoded key, e.g.
+    /// `KAM 12345678 000102030405060708090A0B0C0D0E0F`.
+    /// Empty lines and lines starting with # are ignored.
+    pub fn parse(text: &str) -> Result<Self, ()> {
+        let mut store = Self::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let manufacturer_code = parse_manufacturer(fields.next().ok_or(())?)?;
+            let serial_number = fields.next().ok_or(())?;
+            if serial_number.len() != 8 || !serial_number.bytes().all(|x| x.is_ascii_digit()) {
+                return Err(());
+            }
+            let serial_number = u32::from_str_radix(serial_number, 16).map_err(|_| ())?;
+            let key = parse_key(fields.next().ok_or(())?)?;
+            if fields.next().is_some() {
+                return Err(());
+            }
+
+            store.add_by_serial_number(manufacturer_code, serial_number, key);
+        }
+        Ok(store)
+    }
+}
+
+impl<K> KeyStore<K> for MemoryKeyStore<K> {
+    fn keys(&self, address: &MBusAddress) -> &[K] {
+        self.keys
+            .get(&(address.manufacturer_code, address.serial_number.bcd_value()))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+}
+
+/// A key store loaded from a key file in the format of `MemoryKeyStore::parse()`.
+#[cfg(feature = "std")]
+pub struct FileKeyStore {
+    store: MemoryKeyStore,
+}
+
+#[cfg(feature = "std")]
+impl FileKeyStore {
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let store = MemoryKeyStore::parse(&text)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid key file"))?;
+        Ok(Self { store })
+    }
+}
+
+#[cfg(feature = "std")]
+impl KeyStore for FileKeyStore {
+    fn keys(&self, address: &MBusAddress) -> &[Key] {
+        self.store.keys(address)
+    }
+}
+
+/// Parse the three letter manufacturer code, e.g. KAM.
+fn parse_manufacturer(text: &str) -> Result<u16, ()> {
+    let bytes = text.as_bytes();
+    if bytes.len() != 3 || !bytes.iter().all(u8::is_ascii_uppercase) {
+        return Err(());
+    }
+    Ok(bytes.iter().fold(0, |code, letter| code << 5 | (letter - b'A' + 1) as u16))
+}
+
+fn parse_key(text: &str) -> Result<Key, ()> {
+    if text.len() != 2 * 16 || !text.is_ascii() {
+        return Err(());
+    }
+    let mut key = [0; 16];
+    for (index, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[2 * index..2 * index + 2], 16).map_err(|_| ())?;
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::mbusaddress::{DeviceType, ManufacturerCode};
+
+    use super::*;
+
+    const KEY_FILE: &str = "
+        # Kamstrup water meters
+        KAM 12345678 000102030405060708090A0B0C0D0E0F
+        KAM 12345678 0F0E0D0C0B0A09080706050403020100
+
+        DME 87654321 00000000000000000000000000000000
+    ";
+
+    #[test]
+    pub fn parse_key_file() {
+        let store = MemoryKeyStore::parse(KEY_FILE).unwrap();
+        let keys = store.keys(&MBusAddress::new(ManufacturerCode::KAM, 12345678, 0x01, DeviceType::Water));
+        assert_eq!(2, keys.len());
+        assert_eq!(0x0F, keys[0][15]);
+        assert_eq!(0x0F, keys[1][0]);
+
+        // The version and device type are not part of the lookup
+        let keys = store.keys(&MBusAddress::new(ManufacturerCode::DME, 87654321, 0x20, DeviceType::Heat));
+        assert_eq!(&[[0; 16]], keys);
+
+        assert!(store
+            .keys(&MBusAddress::new(ManufacturerCode::KAM, 12345679, 0x01, DeviceType::Water))
+            .is_empty());
+    }
+
+    #[test]
+    pub fn parse_invalid_key_file() {
+        assert!(MemoryKeyStore::parse("KAM 12345678 000102").is_err());
+        assert!(MemoryKeyStore::parse("KAM 1234567 000102030405060708090A0B0C0D0E0F").is_err());
+        assert!(MemoryKeyStore::parse("KAM 1234567A 000102030405060708090A0B0C0D0E0F").is_err());
+        assert!(MemoryKeyStore::parse("kam 12345678 000102030405060708090A0B0C0D0E0F").is_err());
+        assert!(MemoryKeyStore::parse("KAM 12345678 000102030405060708090A0B0C0D0E0F 00").is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    pub fn load_key_file() {
+        let path = std::env::temp_dir().join("drone-mbus-keys.txt");
+        std::fs::write(&path, KEY_FILE).unwrap();
+        let store = FileKeyStore::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let keys = store.keys(&MBusAddress::new(ManufacturerCode::KAM, 12345678, 0x01, DeviceType::Water));
+        assert_eq!(2, keys.len());
+    }
+}
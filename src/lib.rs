@@ -1,7 +1,7 @@
 #![no_std]
 #![feature(trait_alias)]
 
-#[cfg(test)]
+#[cfg(any(test, feature = "std"))]
 #[macro_use]
 extern crate std;
 
@@ -11,17 +11,59 @@ extern crate alloc;
 #[macro_use]
 extern crate num_derive;
 
+mod authenticationlayer;
 mod bcd;
+mod cifield;
+mod configurationfield;
+mod crypto;
+mod datarecord;
+mod datetime;
+mod duplicatefilter;
 mod ffa;
 mod ffb;
 mod frameformat;
+mod keystore;
+mod messageassembler;
+mod mode;
 mod threeoutofsix;
 mod mbusaddress;
+mod replayguard;
+mod synctracker;
+mod transportlayer;
+mod value;
+mod vif;
 mod wmbus;
 pub mod modec;
+pub mod modef;
+pub mod moden;
+pub mod modes;
 pub mod modet;
+pub mod repeater;
+pub mod session;
 
 pub use self::{
-    wmbus::WMBusPacket,
+    authenticationlayer::{AuthenticationLayer, AuthenticationType},
+    cifield::{CiField, TransportHeader},
+    configurationfield::{ConfigurationField, ConfigurationFieldExtension, SecurityMode},
+    crypto::{kdf_input, CryptoBackend, DerivationConstant, Iv, Key, SoftwareCrypto},
+    datarecord::{DataField, DataRecord, DataRecords, Function, TypedRecord},
+    datetime::{Date, DateTime, DaylightSaving, Time},
+    duplicatefilter::DuplicateFilter,
+    keystore::{KeyStore, MemoryKeyStore, UsedKeys},
+    mbusaddress::{DeviceType, FieldLayout, MBusAddress, ManufacturerCode},
+    messageassembler::MessageAssembler,
+    mode::{Mode, ResponseDelay, ResponseWindow},
+    replayguard::{CounterStore, MemoryCounterStore, ReplayGuard},
+    synctracker::{SyncTracker, TransmissionWindow},
+    transportlayer::{ApplicationStatus, StatusField, TransportLayer},
+    value::Value,
+    vif::{Quantity, RecordError, Unit, ValueInformation, VifModifier},
+    wmbus::{
+        ApplicationLayer, CommunicationControl, EllEncryption, ExtendedLinkLayer, FunctionCode, LinkLayer,
+        WMBusPacket,
+    },
     threeoutofsix::ThreeOutOfSix,
-};
\ No newline at end of file
+};
+
+#[cfg(feature = "std")]
+pub use self::keystore::FileKeyStore;
@@ -2,12 +2,15 @@ use core::{convert::TryInto, fmt::Display};
 
 use crate::bcd::{self, BcdNumber};
 
-#[derive(PartialEq)]
+/// The address of a meter, where the field layout is not part of the identity of the address.
+#[derive(Clone, Copy, Debug)]
 pub struct MBusAddress {
     pub manufacturer_code: u16,
     pub serial_number: BcdNumber<u32>,
     pub version: u8,
     pub device_type: u8,
+    /// The layout of the fields in the transmitted identifier.
+    pub layout: FieldLayout,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, FromPrimitive)]
@@ -42,11 +45,21 @@ pub enum DeviceType {
 
 type Identifier = [u8; 8];
 
-enum FieldLayout {
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldLayout {
     Default, // The default layout according to EN13757, i.e. Manufacturer, serial number, version, type
     Diehl, // The layout used by Diehl on some of its meters, i.e. Manufacturer, version, type, serial number
 }
 
+impl PartialEq for MBusAddress {
+    fn eq(&self, other: &Self) -> bool {
+        self.manufacturer_code == other.manufacturer_code
+            && self.serial_number == other.serial_number
+            && self.version == other.version
+            && self.device_type == other.device_type
+    }
+}
+
 impl Display for MBusAddress {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{:#x}:{:?}/{:?}/{:?}", self.manufacturer_code, self.serial_number, self.version, self.device_type)
@@ -65,6 +78,7 @@ impl MBusAddress {
             serial_number: BcdNumber::encode_u32(serial_number).unwrap(),
             version,
             device_type: device_type as u8,
+            layout: FieldLayout::Default,
         }
     }
 
@@ -88,6 +102,7 @@ impl MBusAddress {
                         serial_number,
                         version: identifier[6],
                         device_type: identifier[7],
+                        layout,
                     })
                 })
             },
@@ -100,12 +115,32 @@ impl MBusAddress {
                         serial_number,
                         version: identifier[2],
                         device_type: identifier[3],
+                        layout,
                     })
                 })
             }
         }
     }
 
+    /// Get the identifier bytes in the field layout of the address, i.e. as transmitted by the meter.
+    pub fn to_bytes(&self) -> Identifier {
+        let mut identifier = [0; 8];
+        identifier[0..2].copy_from_slice(&self.manufacturer_code.to_le_bytes());
+        match self.layout {
+            FieldLayout::Default => {
+                identifier[2..6].copy_from_slice(&self.serial_number.bcd_value().to_le_bytes());
+                identifier[6] = self.version;
+                identifier[7] = self.device_type;
+            }
+            FieldLayout::Diehl => {
+                identifier[2] = self.version;
+                identifier[3] = self.device_type;
+                identifier[4..8].copy_from_slice(&self.serial_number.bcd_value().to_le_bytes());
+            }
+        }
+        identifier
+    }
+
     fn get_layout(identifier: Identifier) -> FieldLayout {
         let manufacturer_code = u16::from_le_bytes(identifier[0..2].try_into().unwrap());
         if manufacturer_code == ManufacturerCode::HYD as u16 {
@@ -156,6 +191,23 @@ pub mod tests {
         assert_eq!(DeviceType::Repeater, address.device_type().unwrap());
     }
 
+    #[test]
+    pub fn to_bytes_default() {
+        let bytes = [0x2D,0x2C,0x78,0x56,0x34,0x12,0x01,0x32];
+        assert_eq!(bytes, MBusAddress::parse(bytes).unwrap().to_bytes());
+    }
+
+    #[test]
+    pub fn to_bytes_diehl() {
+        let bytes = [0x24, 0x23, 0x20, 0x04, 0x69, 0x02, 0x71, 0x47];
+        let address = MBusAddress::parse(bytes).unwrap();
+        assert_eq!(FieldLayout::Diehl, address.layout);
+        assert_eq!(bytes, address.to_bytes());
+
+        // The layout is not part of the identity
+        assert_eq!(MBusAddress::new(ManufacturerCode::HYD, 47710269, 0x20, DeviceType::Heat), address);
+    }
+
     #[test]
     pub fn parse_hydromenter_default() {
         let address = MBusAddress::parse([0x24, 0x23, 0x95, 0x27, 0x80, 0x49, 0x20, 0x0C]).unwrap();
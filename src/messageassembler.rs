@@ -0,0 +1,429 @@
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::{
+    authenticationlayer::AuthenticationLayer,
+    crypto::CryptoBackend,
+    keystore::KeyStore,
+    replayguard::{CounterStore, ReplayGuard},
+    wmbus::WMBusPacket,
+};
+
+/// The fragment id of the first fragment of a message.
+const FIRST_FRAGMENT_ID: u8 = 0;
+
+struct Pending {
+    packet: WMBusPacket,
+    next_fragment_id: u8,
+    started_at: Duration,
+}
+
+/// Assembler for messages carried in the authentication and fragmentation layer.
+/// Fragmented messages are reassembled, and a message is only handed upward when its AES-CMAC or AES-GMAC is verified
+/// and its message counter is accepted by the replay guard.
+/// At most N messages are reassembled at a time, where the oldest is discarded to make room for a new message.
+pub struct MessageAssembler<S: CounterStore, const N: usize> {
+    pending: Vec<Pending>,
+    max_age: Duration,
+    allow_unauthenticated: bool,
+    replay_guard: ReplayGuard<S>,
+}
+
+impl<S: CounterStore, const N: usize> MessageAssembler<S, N> {
+    /// Create an assembler where a message is discarded if it is not complete within max_age of its first fragment.
+    /// Messages without a MAC are rejected unless allow_unauthenticated is set.
+    pub fn new(replay_guard: ReplayGuard<S>, max_age: Duration, allow_unauthenticated: bool) -> Self {
+        Self {
+            pending: Vec::with_capacity(N),
+            max_age,
+            allow_unauthenticated,
+            replay_guard,
+        }
+    }
+
+    pub fn replay_guard(&self) -> &ReplayGuard<S> {
+        &self.replay_guard
+    }
+
+    /// Push a packet received at the instant received_at, where the keys for the MAC verification are looked up by the meter address.
+    /// The complete message is returned when the last fragment is received,
+    /// and packets without the authentication and fragmentation layer are returned as is.
+    pub fn push<B: CryptoBackend, K: KeyStore<B::KeyHandle>>(
+        &mut self,
+        backend: &B,
+        key_store: &K,
+        packet: WMBusPacket,
+        received_at: Duration,
+    ) -> Result<Option<WMBusPacket>, ()> {
+        let afl = match &packet.authentication_layer {
+            Some(afl) => afl.clone(),
+            None => return Ok(Some(packet)),
+        };
+
+        // Discard the messages that were not completed in time
+        let max_age = self.max_age;
+        self.pending.retain(|x| received_at.saturating_sub(x.started_at) <= max_age);
+
+        let address = packet.link_layer.address;
+        let pending = self
+            .pending
+            .iter()
+            .position(|x| x.packet.link_layer.address == address)
+            .map(|index| self.pending.swap_remove(index));
+        let message = match pending {
+            Some(mut pending) if afl.fragment_id() == pending.next_fragment_id => {
+                append_fragment(&mut pending.packet, packet)?;
+                pending.next_fragment_id = afl.fragment_id().wrapping_add(1);
+                pending
+            }
+            // A new message replaces the pending message, e.g. when the meter restarts after a lost fragment
+            _ if afl.fragment_id() == FIRST_FRAGMENT_ID => Pending {
+                packet,
+                next_fragment_id: afl.fragment_id().wrapping_add(1),
+                started_at: received_at,
+            },
+            // The first fragments of the message were missed
+            _ => return Err(()),
+        };
+
+        if afl.more_fragments() {
+            if N == 0 {
+                return Err(());
+            }
+            if self.pending.len() == N {
+                let oldest = (0..N).min_by_key(|&index| self.pending[index].started_at).unwrap();
+                self.pending.swap_remove(oldest);
+            }
+            self.pending.push(message);
+            return Ok(None);
+        }
+
+        let mut message = message.packet;
+        if let Some(afl) = &mut message.authentication_layer {
+            afl.fcl &= !AuthenticationLayer::MORE_FRAGMENTS;
+        }
+        self.verify(backend, key_store, &message)?;
+        Ok(Some(message))
+    }
+
+    fn verify<B: CryptoBackend, K: KeyStore<B::KeyHandle>>(
+        &mut self,
+        backend: &B,
+        key_store: &K,
+        message: &WMBusPacket,
+    ) -> Result<(), ()> {
+        let afl = message.authentication_layer.as_ref().ok_or(())?;
+        let apl = message.application_layer.as_ref().ok_or(())?;
+        if let Some(ml) = afl.ml {
+            if ml as usize != 1 + apl.data.len() {
+                return Err(());
+            }
+        }
+
+        // The message counter can only be trusted when it is authenticated by the MAC
+        if afl.mac.is_none() {
+            if afl.mcr.is_some() || !self.allow_unauthenticated {
+                return Err(());
+            }
+            return Ok(());
+        }
+
+        self.replay_guard.check_packet(message)?;
+        let keys = key_store.keys(&message.meter_address());
+        if !keys.iter().any(|key| message.verify_mac(backend, key).is_ok()) {
+            return Err(());
+        }
+        self.replay_guard.commit_packet(message);
+        Ok(())
+    }
+}
+
+/// Append the application layer of the fragment to the message,
+/// and take the authentication and fragmentation layer fields not present in the message from the fragment.
+fn append_fragment(message: &mut WMBusPacket, fragment: WMBusPacket) -> Result<(), ()> {
+    let apl = message.application_layer.as_mut().ok_or(())?;
+    let fragment_apl = fragment.application_layer.ok_or(())?;
+    apl.data.push(fragment_apl.ci);
+    apl.data.extend_from_slice(&fragment_apl.data);
+
+    let afl = message.authentication_layer.as_mut().ok_or(())?;
+    let fragment_afl = fragment.authentication_layer.ok_or(())?;
+    afl.mcl = afl.mcl.or(fragment_afl.mcl);
+    afl.ki = afl.ki.or(fragment_afl.ki);
+    afl.mcr = afl.mcr.or(fragment_afl.mcr);
+    afl.mac = afl.mac.take().or(fragment_afl.mac);
+    afl.ml = afl.ml.or(fragment_afl.ml);
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        authenticationlayer::AuthenticationType,
+        crypto::{Key, SoftwareCrypto},
+        keystore::MemoryKeyStore,
+        mbusaddress::{DeviceType, MBusAddress, ManufacturerCode},
+        replayguard::MemoryCounterStore,
+        wmbus::{tests::mode10_encrypted_payload, ApplicationLayer},
+    };
+
+    use super::*;
+
+    const KEY: Key = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F,
+    ];
+
+    fn key_store() -> MemoryKeyStore {
+        let mut key_store = MemoryKeyStore::new();
+        key_store.add(
+            &MBusAddress::new(ManufacturerCode::KAM, 12345678, 0x01, DeviceType::Water),
+            KEY,
+        );
+        key_store
+    }
+
+    fn assembler() -> MessageAssembler<MemoryCounterStore<4>, 2> {
+        MessageAssembler::new(ReplayGuard::new(MemoryCounterStore::new(), None), Duration::from_secs(10), false)
+    }
+
+    fn mode7_packet(counter: u32) -> WMBusPacket {
+        let mut payload = vec![
+            0x00, 0x44, 0x2D, 0x2C, 0x78, 0x56, 0x34, 0x12, 0x01, 0x07, // Link layer
+            0x7A, 0x5A, 0x00, 0x00, 0x00, // Short transport layer
+            0x04, 0x13, 0x39, 0x30, 0x00, 0x00, 0x04, 0x6D, 0x32, 0x37, 0x1F, 0x15, 0x02, 0xFD, 0x17, 0x00, 0x00,
+        ];
+        payload[0] = (payload.len() - 1) as u8;
+        let mut packet = WMBusPacket::parse_payload(&payload).unwrap();
+        packet.encrypt_mode7(&SoftwareCrypto, &KEY, counter).unwrap();
+        packet
+    }
+
+    /// Split the message in two fragments.
+    fn fragments(message: &WMBusPacket, split: usize) -> (WMBusPacket, WMBusPacket) {
+        let apl = message.application_layer.as_ref().unwrap();
+        let mut bytes = vec![apl.ci];
+        bytes.extend_from_slice(&apl.data);
+
+        let mut first = message.clone();
+        let afl = first.authentication_layer.as_mut().unwrap();
+        afl.fcl = AuthenticationLayer::MORE_FRAGMENTS | FIRST_FRAGMENT_ID as u16;
+        afl.ml = Some(bytes.len() as u16);
+        first.application_layer = Some(ApplicationLayer {
+            ci: bytes[0],
+            data: bytes[1..split].to_vec(),
+        });
+
+        let mut second = message.clone();
+        second.authentication_layer = Some(AuthenticationLayer {
+            fcl: FIRST_FRAGMENT_ID as u16 + 1,
+            mcl: None,
+            ki: None,
+            mcr: None,
+            mac: None,
+            ml: None,
+        });
+        second.application_layer = Some(ApplicationLayer {
+            ci: bytes[split],
+            data: bytes[split + 1..].to_vec(),
+        });
+
+        (first, second)
+    }
+
+    #[test]
+    pub fn passes_verified_message() {
+        let mut assembler = assembler();
+        let message = assembler
+            .push(&SoftwareCrypto, &key_store(), mode7_packet(1), Duration::ZERO)
+            .unwrap()
+            .unwrap();
+        assert_eq!(Some(1), message.authentication_layer.unwrap().mcr);
+
+        // Packets without the authentication and fragmentation layer are not verified
+        let mut packet = mode7_packet(2);
+        packet.authentication_layer = None;
+        assert!(assembler
+            .push(&SoftwareCrypto, &MemoryKeyStore::new(), packet, Duration::ZERO)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    pub fn rejects_replayed_message() {
+        let mut assembler = assembler();
+        let key_store = key_store();
+        assert!(assembler.push(&SoftwareCrypto, &key_store, mode7_packet(2), Duration::ZERO).is_ok());
+        assert!(assembler.push(&SoftwareCrypto, &key_store, mode7_packet(2), Duration::ZERO).is_err());
+        assert!(assembler.push(&SoftwareCrypto, &key_store, mode7_packet(1), Duration::ZERO).is_err());
+        assert!(assembler.push(&SoftwareCrypto, &key_store, mode7_packet(3), Duration::ZERO).is_ok());
+    }
+
+    #[test]
+    pub fn rejects_forged_message_counter() {
+        let mut assembler = assembler();
+        let key_store = key_store();
+        let address = MBusAddress::new(ManufacturerCode::KAM, 12345678, 0x01, DeviceType::Water);
+        assert!(assembler.push(&SoftwareCrypto, &key_store, mode7_packet(1), Duration::ZERO).is_ok());
+
+        let mut forged = mode7_packet(2);
+        forged.authentication_layer.as_mut().unwrap().mcr = Some(0xFFFF_FFFF);
+        assert!(assembler.push(&SoftwareCrypto, &key_store, forged, Duration::ZERO).is_err());
+
+        // Without a MAC
+        let mut forged = mode7_packet(2);
+        let afl = forged.authentication_layer.as_mut().unwrap();
+        afl.mcr = Some(0xFFFF_FFFF);
+        afl.mac = None;
+        assert!(assembler.push(&SoftwareCrypto, &key_store, forged, Duration::ZERO).is_err());
+
+        // With a GMAC that is not verified by the decryption
+        let mut forged = mode7_packet(2);
+        let afl = forged.authentication_layer.as_mut().unwrap();
+        afl.mcl = Some(AuthenticationType::AesGmac96 as u8);
+        afl.mcr = Some(0xFFFF_FFFF);
+        afl.mac = Some(vec![0; 12]);
+        assert!(assembler.push(&SoftwareCrypto, &key_store, forged, Duration::ZERO).is_err());
+
+        assert_eq!(Some(1), assembler.replay_guard().store().load(&address));
+        assert!(assembler.push(&SoftwareCrypto, &key_store, mode7_packet(2), Duration::ZERO).is_ok());
+    }
+
+    #[test]
+    pub fn passes_verified_gmac_message() {
+        let mut assembler = assembler();
+        let packet = WMBusPacket::parse_payload(&mode10_encrypted_payload()).unwrap();
+        let address = packet.meter_address();
+        assert!(assembler.push(&SoftwareCrypto, &key_store(), packet, Duration::ZERO).unwrap().is_some());
+        assert_eq!(Some(0x0102), assembler.replay_guard().store().load(&address));
+
+        let mut packet = WMBusPacket::parse_payload(&mode10_encrypted_payload()).unwrap();
+        packet.authentication_layer.as_mut().unwrap().mcr = Some(0x0103);
+        assert!(assembler.push(&SoftwareCrypto, &key_store(), packet, Duration::ZERO).is_err());
+    }
+
+    #[test]
+    pub fn rejects_unauthenticated_message() {
+        let mut packet = mode7_packet(1);
+        let afl = packet.authentication_layer.as_mut().unwrap();
+        afl.mcl = None;
+        afl.mcr = None;
+        afl.mac = None;
+        assert!(assembler().push(&SoftwareCrypto, &key_store(), packet.clone(), Duration::ZERO).is_err());
+
+        let mut assembler: MessageAssembler<MemoryCounterStore<4>, 2> =
+            MessageAssembler::new(ReplayGuard::new(MemoryCounterStore::new(), None), Duration::from_secs(10), true);
+        assert!(assembler.push(&SoftwareCrypto, &key_store(), packet, Duration::ZERO).unwrap().is_some());
+    }
+
+    #[test]
+    pub fn rejects_invalid_mac() {
+        let mut assembler = assembler();
+        let mut packet = mode7_packet(1);
+        packet.application_layer.as_mut().unwrap().data.push(0x00);
+        assert!(assembler.push(&SoftwareCrypto, &key_store(), packet, Duration::ZERO).is_err());
+
+        // The message counter is not updated by messages that are not verified
+        assert!(assembler.push(&SoftwareCrypto, &key_store(), mode7_packet(1), Duration::ZERO).is_ok());
+    }
+
+    #[test]
+    pub fn reassembles_fragments() {
+        let mut assembler = assembler();
+        let key_store = key_store();
+        let message = mode7_packet(1);
+        let (first, second) = fragments(&message, 10);
+
+        assert!(assembler.push(&SoftwareCrypto, &key_store, first, Duration::ZERO).unwrap().is_none());
+        let mut reassembled = assembler.push(&SoftwareCrypto, &key_store, second, Duration::ZERO).unwrap().unwrap();
+        assert!(!reassembled.authentication_layer.as_ref().unwrap().more_fragments());
+        assert_eq!(
+            message.application_layer.as_ref().unwrap().data,
+            reassembled.application_layer.as_ref().unwrap().data
+        );
+
+        reassembled.decrypt_tpl(&SoftwareCrypto, &KEY).unwrap();
+        assert_eq!(
+            &[0x2F, 0x2F, 0x04, 0x13, 0x39, 0x30, 0x00, 0x00],
+            &reassembled.application_layer.unwrap().application_data().unwrap()[..8]
+        );
+    }
+
+    #[test]
+    pub fn rejects_missing_fragment() {
+        let mut assembler = assembler();
+        let key_store = key_store();
+        let (first, mut second) = fragments(&mode7_packet(1), 10);
+        second.authentication_layer.as_mut().unwrap().fcl = 0x03;
+
+        assert!(assembler.push(&SoftwareCrypto, &key_store, first, Duration::ZERO).unwrap().is_none());
+        assert!(assembler.push(&SoftwareCrypto, &key_store, second, Duration::ZERO).is_err());
+    }
+
+    #[test]
+    pub fn restarts_message_with_first_fragment() {
+        let mut assembler = assembler();
+        let key_store = key_store();
+        let (first, _) = fragments(&mode7_packet(1), 10);
+        let (restarted, second) = fragments(&mode7_packet(2), 10);
+
+        assert!(assembler.push(&SoftwareCrypto, &key_store, first, Duration::ZERO).unwrap().is_none());
+        assert!(assembler.push(&SoftwareCrypto, &key_store, restarted, Duration::ZERO).unwrap().is_none());
+        let message = assembler.push(&SoftwareCrypto, &key_store, second, Duration::ZERO).unwrap().unwrap();
+        assert_eq!(Some(2), message.authentication_layer.unwrap().mcr);
+    }
+
+    #[test]
+    pub fn rejects_message_without_first_fragment() {
+        let mut assembler = assembler();
+        let (_, second) = fragments(&mode7_packet(1), 10);
+        assert!(assembler.push(&SoftwareCrypto, &key_store(), second, Duration::ZERO).is_err());
+
+        let (mut first, _) = fragments(&mode7_packet(1), 10);
+        first.authentication_layer.as_mut().unwrap().fcl += 1;
+        assert!(assembler.push(&SoftwareCrypto, &key_store(), first, Duration::ZERO).is_err());
+    }
+
+    #[test]
+    pub fn discards_expired_message() {
+        let mut assembler = assembler();
+        let key_store = key_store();
+        let (first, second) = fragments(&mode7_packet(1), 10);
+        assert!(assembler.push(&SoftwareCrypto, &key_store, first, Duration::ZERO).unwrap().is_none());
+        assert!(assembler
+            .push(&SoftwareCrypto, &key_store, second, Duration::from_secs(11))
+            .is_err());
+    }
+
+    #[test]
+    pub fn discards_oldest_message() {
+        let key_store = key_store();
+        let first_from = |serial_number| {
+            let (mut first, _) = fragments(&mode7_packet(1), 10);
+            first.link_layer.address = MBusAddress::new(ManufacturerCode::KAM, serial_number, 0x01, DeviceType::Water);
+            first
+        };
+
+        {
+            // The message from the meter is the oldest when the third message starts
+            let mut assembler = assembler();
+            let (first, second) = fragments(&mode7_packet(1), 10);
+            assert!(assembler.push(&SoftwareCrypto, &key_store, first, Duration::from_secs(0)).is_ok());
+            assert!(assembler.push(&SoftwareCrypto, &key_store, first_from(1), Duration::from_secs(1)).is_ok());
+            assert!(assembler.push(&SoftwareCrypto, &key_store, first_from(2), Duration::from_secs(2)).is_ok());
+            assert!(assembler.push(&SoftwareCrypto, &key_store, second, Duration::from_secs(3)).is_err());
+        }
+
+        {
+            // The message from the meter is kept as it is not the oldest
+            let mut assembler = assembler();
+            let (first, second) = fragments(&mode7_packet(1), 10);
+            assert!(assembler.push(&SoftwareCrypto, &key_store, first_from(1), Duration::from_secs(0)).is_ok());
+            assert!(assembler.push(&SoftwareCrypto, &key_store, first, Duration::from_secs(1)).is_ok());
+            assert!(assembler.push(&SoftwareCrypto, &key_store, first_from(2), Duration::from_secs(2)).is_ok());
+            assert!(assembler
+                .push(&SoftwareCrypto, &key_store, second, Duration::from_secs(3))
+                .unwrap()
+                .is_some());
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use core::time::Duration;
+
+use crate::{modec, modef, moden, modes, modet, wmbus::CommunicationControl};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    S,
+    T,
+    C,
+    N,
+    F,
+}
+
+/// The response delay tRO measured from the end of the received frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResponseDelay {
+    pub min: Duration,
+    pub max: Duration,
+}
+
+/// The instants where the transmission of the response must start.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResponseWindow {
+    pub earliest: Duration,
+    pub latest: Duration,
+}
+
+impl Mode {
+    /// Get the time on air for frame_size bytes following the syncword from the meter.
+    pub fn frame_duration(self, frame_size: usize) -> Duration {
+        let (chiprate, chips_per_byte) = match self {
+            Mode::S => (modes::CHIPRATE, modes::CHIPS_PER_BYTE),
+            Mode::T => (modet::CHIPRATE, modet::CHIPS_PER_BYTE),
+            Mode::C => (modec::CHIPRATE, modec::CHIPS_PER_BYTE),
+            Mode::N => (moden::CHIPRATE, moden::CHIPS_PER_BYTE),
+            Mode::F => (modef::CHIPRATE, modef::CHIPS_PER_BYTE),
+        };
+        let chips = frame_size as u64 * chips_per_byte as u64;
+        Duration::from_nanos(chips * 1_000_000_000 / chiprate as u64)
+    }
+
+    /// Get the response delay for a frame from the meter.
+    /// The C, N and F modes use the fast response delay if it is requested in the CC field, otherwise the slow.
+    pub fn response_delay(self, cc: Option<CommunicationControl>) -> ResponseDelay {
+        let fast = cc.map(|cc| cc.fast_response_delay()).unwrap_or(false);
+        match self {
+            Mode::S => modes::RESPONSE_DELAY,
+            Mode::T => modet::RESPONSE_DELAY,
+            Mode::C if fast => modec::RESPONSE_DELAY_FAST,
+            Mode::C => modec::RESPONSE_DELAY_SLOW,
+            Mode::N if fast => moden::RESPONSE_DELAY_FAST,
+            Mode::N => moden::RESPONSE_DELAY_SLOW,
+            Mode::F if fast => modef::RESPONSE_DELAY_FAST,
+            Mode::F => modef::RESPONSE_DELAY_SLOW,
+        }
+    }
+
+    /// Get the window for the transmission start of the response to a frame from the meter.
+    /// The frame size is the number of bytes following the syncword, and received_at is the instant of the syncword detection.
+    pub fn response_window(
+        self,
+        frame_size: usize,
+        cc: Option<CommunicationControl>,
+        received_at: Duration,
+    ) -> ResponseWindow {
+        let frame_end = received_at + self.frame_duration(frame_size);
+        let delay = self.response_delay(cc);
+        ResponseWindow {
+            earliest: frame_end + delay.min,
+            latest: frame_end + delay.max,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn frame_duration() {
+        assert_eq!(Duration::from_micros(1200), Mode::T.frame_duration(10));
+        assert_eq!(Duration::from_micros(800), Mode::C.frame_duration(10));
+        assert_eq!(Duration::from_nanos(4_882_812), Mode::S.frame_duration(10));
+        assert_eq!(Duration::from_nanos(16_666_666), Mode::N.frame_duration(10));
+        assert_eq!(Duration::from_nanos(33_333_333), Mode::F.frame_duration(10));
+    }
+
+    #[test]
+    pub fn t2_response_window() {
+        let window = Mode::T.response_window(20, None, Duration::from_millis(1000));
+        assert_eq!(Duration::from_micros(1_000_000 + 2_400 + 2_000), window.earliest);
+        assert_eq!(Duration::from_micros(1_000_000 + 2_400 + 3_000), window.latest);
+    }
+
+    #[test]
+    pub fn c2_response_window_uses_cc_response_delay() {
+        let fast = CommunicationControl(CommunicationControl::BIDIRECTIONAL | CommunicationControl::RESPONSE_DELAY);
+        let window = Mode::C.response_window(20, Some(fast), Duration::ZERO);
+        assert_eq!(Duration::from_micros(1_600 + 99_500), window.earliest);
+        assert_eq!(Duration::from_micros(1_600 + 100_500), window.latest);
+
+        let slow = CommunicationControl(CommunicationControl::BIDIRECTIONAL);
+        let window = Mode::C.response_window(20, Some(slow), Duration::ZERO);
+        assert_eq!(Duration::from_micros(1_600 + 999_500), window.earliest);
+        assert_eq!(Duration::from_micros(1_600 + 1_000_500), window.latest);
+    }
+
+    #[test]
+    pub fn s2_ignores_cc_response_delay() {
+        let fast = CommunicationControl(CommunicationControl::RESPONSE_DELAY);
+        assert_eq!(modes::RESPONSE_DELAY, Mode::S.response_delay(Some(fast)));
+        assert_eq!(modes::RESPONSE_DELAY, Mode::S.response_delay(None));
+    }
+}
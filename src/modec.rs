@@ -1,2 +1,15 @@
+use core::time::Duration;
+
+use crate::mode::ResponseDelay;
+
 pub const SYNCWORD: u32 = 0x543D543D;
-pub const CHIPRATE: u32 = 100_000; // kcps
\ No newline at end of file
+pub const CHIPRATE: u32 = 100_000; // chips/s
+pub const CHIPS_PER_BYTE: u32 = 8; // NRZ
+pub const RESPONSE_DELAY_FAST: ResponseDelay = ResponseDelay {
+    min: Duration::from_micros(99_500),
+    max: Duration::from_micros(100_500),
+};
+pub const RESPONSE_DELAY_SLOW: ResponseDelay = ResponseDelay {
+    min: Duration::from_micros(999_500),
+    max: Duration::from_micros(1_000_500),
+};
@@ -0,0 +1,14 @@
+use core::time::Duration;
+
+use crate::mode::ResponseDelay;
+
+pub const CHIPRATE: u32 = 4_800; // chips/s, the channels 1a, 1b, 3a and 3b
+pub const CHIPS_PER_BYTE: u32 = 8; // NRZ
+pub const RESPONSE_DELAY_FAST: ResponseDelay = ResponseDelay {
+    min: Duration::from_micros(99_500),
+    max: Duration::from_micros(100_500),
+};
+pub const RESPONSE_DELAY_SLOW: ResponseDelay = ResponseDelay {
+    min: Duration::from_micros(999_500),
+    max: Duration::from_micros(1_000_500),
+};
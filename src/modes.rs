@@ -0,0 +1,11 @@
+use core::time::Duration;
+
+use crate::mode::ResponseDelay;
+
+pub const SYNCWORD: u16 = 0x7696;
+pub const CHIPRATE: u32 = 32_768; // chips/s
+pub const CHIPS_PER_BYTE: u32 = 16; // Manchester
+pub const RESPONSE_DELAY: ResponseDelay = ResponseDelay {
+    min: Duration::from_millis(3),
+    max: Duration::from_millis(50),
+};
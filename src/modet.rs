@@ -1,2 +1,11 @@
+use core::time::Duration;
+
+use crate::mode::ResponseDelay;
+
 pub const SYNCWORD: u16 = 0x543D;
-pub const CHIPRATE: u32 = 100_000; // kcps
\ No newline at end of file
+pub const CHIPRATE: u32 = 100_000; // chips/s
+pub const CHIPS_PER_BYTE: u32 = 12; // 3 out of 6 encoding
+pub const RESPONSE_DELAY: ResponseDelay = ResponseDelay {
+    min: Duration::from_millis(2),
+    max: Duration::from_millis(3),
+};
@@ -0,0 +1,117 @@
+use alloc::vec::Vec;
+
+use crate::{
+    ffa::FrameFormatA,
+    ffb::FrameFormatB,
+    frameformat::FrameFormat,
+    mbusaddress::DeviceType,
+    wmbus::{self, CommunicationControl, WMBusPacket},
+};
+
+const CC_OFFSET: usize = 1 + 1 + 8 + 1; // L, C, M, A and the CI field of the extended link layer
+
+/// Get whether a packet may be repeated by a single hop repeater.
+/// The hop count is carried in the extended link layer, so packets without it cannot be repeated,
+/// and packets already repeated or transmitted by a repeater are not repeated again.
+pub fn is_repeatable(packet: &WMBusPacket) -> bool {
+    match &packet.ext_link_layer {
+        Some(ell) => {
+            ell.communication_control().hop_count() == 0
+                && packet.link_layer.address.device_type() != Some(DeviceType::Repeater)
+        }
+        None => false,
+    }
+}
+
+pub fn repeat_ffa(frame_bytes: &[u8]) -> Result<Vec<u8>, ()> {
+    repeat(FrameFormatA, frame_bytes)
+}
+
+pub fn repeat_ffb(frame_bytes: &[u8]) -> Result<Vec<u8>, ()> {
+    repeat(FrameFormatB, frame_bytes)
+}
+
+/// Create the repeated frame from the received frame.
+/// Only the hop count is changed, so the payload is repeated byte by byte with new CRC fields.
+fn repeat<FF: FrameFormat + Copy>(frame_format: FF, frame_bytes: &[u8]) -> Result<Vec<u8>, ()> {
+    let mut payload = wmbus::payload(frame_format, frame_bytes)?;
+    let packet = WMBusPacket::parse_payload(&payload)?;
+    if !is_repeatable(&packet) {
+        return Err(());
+    }
+
+    payload[CC_OFFSET] = CommunicationControl(payload[CC_OFFSET]).repeated().0;
+    wmbus::frame(frame_format, payload)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        mbusaddress::{MBusAddress, ManufacturerCode},
+        wmbus::{ApplicationLayer, ExtendedLinkLayer, LinkLayer},
+    };
+
+    use super::*;
+
+    fn packet(device_type: DeviceType, cc: u8) -> WMBusPacket {
+        WMBusPacket {
+            application_layer: Some(ApplicationLayer {
+                ci: 0x7A,
+                data: vec![0x10, 0x00, 0x00, 0x00, 0x2F, 0x2F],
+            }),
+            authentication_layer: None,
+            ext_link_layer: Some(ExtendedLinkLayer::Short { cc, acc: 0x10 }),
+            link_layer: LinkLayer {
+                length: None,
+                control: 0x44,
+                address: MBusAddress::new(ManufacturerCode::KAM, 12345678, 0x01, device_type),
+            },
+        }
+    }
+
+    #[test]
+    pub fn can_repeat_ffa() {
+        let frame_bytes = packet(DeviceType::Water, CommunicationControl::SYNCHRONIZED)
+            .to_ffa()
+            .unwrap();
+
+        let repeated = WMBusPacket::parse_ffa(&repeat_ffa(&frame_bytes).unwrap()).unwrap();
+        let ell = repeated.ext_link_layer.as_ref().unwrap();
+        assert_eq!(1, ell.communication_control().hop_count());
+        assert!(ell.communication_control().synchronized());
+        assert_eq!(0x10, ell.acc());
+        assert_eq!(
+            vec![0x10, 0x00, 0x00, 0x00, 0x2F, 0x2F],
+            repeated.application_layer.unwrap().data
+        );
+    }
+
+    #[test]
+    pub fn can_repeat_ffb() {
+        let frame_bytes = packet(DeviceType::Water, 0x00).to_ffb().unwrap();
+
+        let repeated = WMBusPacket::parse_ffb(&repeat_ffb(&frame_bytes).unwrap()).unwrap();
+        assert_eq!(
+            1,
+            repeated.ext_link_layer.unwrap().communication_control().hop_count()
+        );
+    }
+
+    #[test]
+    pub fn does_not_repeat_twice() {
+        let frame_bytes = packet(DeviceType::Water, 0x00).to_ffa().unwrap();
+        let repeated = repeat_ffa(&frame_bytes).unwrap();
+        assert!(repeat_ffa(&repeated).is_err());
+    }
+
+    #[test]
+    pub fn repeatable() {
+        assert!(is_repeatable(&packet(DeviceType::Water, 0x00)));
+        assert!(!is_repeatable(&packet(DeviceType::Water, CommunicationControl::HOP_COUNT)));
+        assert!(!is_repeatable(&packet(DeviceType::Repeater, 0x00)));
+
+        let mut without_ell = packet(DeviceType::Water, 0x00);
+        without_ell.ext_link_layer = None;
+        assert!(!is_repeatable(&without_ell));
+    }
+}
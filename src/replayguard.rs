@@ -0,0 +1,228 @@
+use crate::{mbusaddress::MBusAddress, wmbus::WMBusPacket};
+
+/// Storage of the last accepted message counter per meter, e.g. persisted in flash.
+pub trait CounterStore {
+    fn load(&self, address: &MBusAddress) -> Option<u32>;
+    fn store(&mut self, address: &MBusAddress, counter: u32);
+}
+
+/// In-memory counter store that remembers the N most recently updated meters.
+/// The least recently updated meter is forgotten when a new meter is stored in a full store,
+/// after which a replayed frame from the forgotten meter is accepted as its first frame.
+/// N should therefore exceed the number of meters in range.
+pub struct MemoryCounterStore<const N: usize> {
+    /// The entries from the least to the most recently updated.
+    entries: [Option<(MBusAddress, u32)>; N],
+}
+
+impl<const N: usize> MemoryCounterStore<N> {
+    pub fn new() -> Self {
+        Self { entries: [None; N] }
+    }
+}
+
+impl<const N: usize> Default for MemoryCounterStore<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> CounterStore for MemoryCounterStore<N> {
+    fn load(&self, address: &MBusAddress) -> Option<u32> {
+        self.entries
+            .iter()
+            .flatten()
+            .find(|(x, _)| x == address)
+            .map(|(_, counter)| *counter)
+    }
+
+    fn store(&mut self, address: &MBusAddress, counter: u32) {
+        let len = self.entries.iter().flatten().count();
+        let (index, end) = match self.entries.iter().flatten().position(|(x, _)| x == address) {
+            Some(index) => (index, len),
+            None if len < N => (len, len + 1),
+            // Evict the least recently updated meter
+            None if N > 0 => (0, N),
+            None => return,
+        };
+
+        // Move the entry to the end as the most recently updated
+        self.entries[index..end].rotate_left(1);
+        self.entries[end - 1] = Some((*address, counter));
+    }
+}
+
+/// Rejection of replayed telegrams by enforcing a strictly increasing message counter per meter.
+pub struct ReplayGuard<S: CounterStore> {
+    store: S,
+    reset_tolerance: Option<u32>,
+}
+
+impl<S: CounterStore> ReplayGuard<S> {
+    /// Create a guard where a counter lower than the last accepted is accepted as a counter reset,
+    /// e.g. after battery replacement, if it is at most the reset tolerance and the last accepted counter is not.
+    /// Counter resets are rejected if the reset tolerance is None.
+    /// The counters must only be committed for authenticated frames, as a single forged frame could otherwise
+    /// use up the counter reset, or lock out the meter with a high counter.
+    pub fn new(store: S, reset_tolerance: Option<u32>) -> Self {
+        Self { store, reset_tolerance }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Check that the message counter from the meter is newer than the last accepted, without remembering it.
+    pub fn check(&self, address: &MBusAddress, counter: u32) -> Result<(), ()> {
+        if let Some(last) = self.store.load(address) {
+            let reset = matches!(self.reset_tolerance, Some(tolerance) if counter <= tolerance && last > tolerance);
+            if counter <= last && !reset {
+                return Err(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Remember the message counter from the meter as the last accepted.
+    /// The counter must only be committed after the MAC or the GCM tag of the frame is verified.
+    pub fn commit(&mut self, address: &MBusAddress, counter: u32) {
+        self.store.store(address, counter);
+    }
+
+    /// Check and commit the message counter from the meter, which must only be used for authenticated frames.
+    pub fn accept(&mut self, address: &MBusAddress, counter: u32) -> Result<(), ()> {
+        self.check(address, counter)?;
+        self.commit(address, counter);
+        Ok(())
+    }
+
+    /// Check the message counter of the packet from the meter,
+    /// i.e. the counter in the authentication and fragmentation layer, or the session number in the extended link layer.
+    /// Packets without a message counter are accepted.
+    pub fn check_packet(&self, packet: &WMBusPacket) -> Result<(), ()> {
+        match message_counter(packet) {
+            Some(counter) => self.check(&packet.meter_address(), counter),
+            None => Ok(()),
+        }
+    }
+
+    /// Commit the message counter of the packet from the meter after the packet is authenticated.
+    pub fn commit_packet(&mut self, packet: &WMBusPacket) {
+        if let Some(counter) = message_counter(packet) {
+            self.commit(&packet.meter_address(), counter);
+        }
+    }
+}
+
+fn message_counter(packet: &WMBusPacket) -> Option<u32> {
+    let afl_counter = packet.authentication_layer.as_ref().and_then(|afl| afl.mcr);
+    let ell_counter = packet.ext_link_layer.as_ref().and_then(|ell| ell.session_number());
+    afl_counter.or(ell_counter)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        mbusaddress::{DeviceType, ManufacturerCode},
+        wmbus::{ExtendedLinkLayer, LinkLayer},
+    };
+
+    use super::*;
+
+    fn address(serial_number: u32) -> MBusAddress {
+        MBusAddress::new(ManufacturerCode::KAM, serial_number, 0x01, DeviceType::Water)
+    }
+
+    #[test]
+    pub fn enforces_increasing_counter() {
+        let mut guard = ReplayGuard::new(MemoryCounterStore::<4>::new(), None);
+        assert!(guard.accept(&address(1), 10).is_ok());
+        assert!(guard.accept(&address(1), 10).is_err());
+        assert!(guard.accept(&address(1), 9).is_err());
+        assert!(guard.accept(&address(1), 11).is_ok());
+
+        // Counters are per meter
+        assert!(guard.accept(&address(2), 1).is_ok());
+        assert_eq!(Some(11), guard.store().load(&address(1)));
+    }
+
+    #[test]
+    pub fn check_does_not_remember_counter() {
+        let mut guard = ReplayGuard::new(MemoryCounterStore::<4>::new(), None);
+        assert!(guard.check(&address(1), 10).is_ok());
+        assert!(guard.check(&address(1), 10).is_ok());
+        assert_eq!(None, guard.store().load(&address(1)));
+
+        guard.commit(&address(1), 10);
+        assert!(guard.check(&address(1), 10).is_err());
+        assert!(guard.check(&address(1), 11).is_ok());
+    }
+
+    #[test]
+    pub fn tolerates_counter_reset() {
+        let mut guard = ReplayGuard::new(MemoryCounterStore::<4>::new(), Some(100));
+        assert!(guard.accept(&address(1), 5000).is_ok());
+        assert!(guard.accept(&address(1), 101).is_err());
+        assert!(guard.accept(&address(1), 3).is_ok());
+
+        // Only a single reset is accepted until the counter exceeds the tolerance again
+        assert!(guard.accept(&address(1), 2).is_err());
+        assert!(guard.accept(&address(1), 4).is_ok());
+    }
+
+    #[test]
+    pub fn counters_are_persisted() {
+        let mut guard = ReplayGuard::new(MemoryCounterStore::<4>::new(), None);
+        guard.accept(&address(1), 10).unwrap();
+
+        let mut guard = ReplayGuard::new(guard.into_store(), None);
+        assert!(guard.accept(&address(1), 10).is_err());
+    }
+
+    #[test]
+    pub fn evicts_least_recently_updated_meter() {
+        let mut guard = ReplayGuard::new(MemoryCounterStore::<2>::new(), None);
+        guard.accept(&address(1), 10).unwrap();
+        guard.accept(&address(2), 20).unwrap();
+        guard.accept(&address(1), 11).unwrap();
+        guard.accept(&address(3), 30).unwrap();
+
+        assert_eq!(Some(11), guard.store().load(&address(1)));
+        assert_eq!(None, guard.store().load(&address(2)));
+        assert_eq!(Some(30), guard.store().load(&address(3)));
+
+        // The evicted meter is not protected against a replay of its old frames
+        assert!(guard.accept(&address(2), 20).is_ok());
+        assert_eq!(None, guard.store().load(&address(1)));
+    }
+
+    #[test]
+    pub fn accepts_ell_session_number() {
+        let mut guard = ReplayGuard::new(MemoryCounterStore::<4>::new(), None);
+        let packet = |sn| WMBusPacket {
+            application_layer: None,
+            authentication_layer: None,
+            ext_link_layer: Some(ExtendedLinkLayer::Long {
+                cc: 0x20,
+                acc: 0x01,
+                sn,
+                payload_crc: None,
+            }),
+            link_layer: LinkLayer {
+                length: None,
+                control: 0x44,
+                address: address(1),
+            },
+        };
+
+        assert!(guard.check_packet(&packet(0x2000_0010)).is_ok());
+        guard.commit_packet(&packet(0x2000_0010));
+        // The encryption bits are not part of the session number
+        assert!(guard.check_packet(&packet(0x0000_0010)).is_err());
+        assert!(guard.check_packet(&packet(0x0000_0011)).is_ok());
+    }
+}
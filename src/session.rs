@@ -0,0 +1,403 @@
+use alloc::{collections::VecDeque, vec::Vec};
+use core::time::Duration;
+
+use crate::{
+    mbusaddress::MBusAddress,
+    mode::Mode,
+    wmbus::{ApplicationLayer, CommunicationControl, ExtendedLinkLayer, FunctionCode, LinkLayer, WMBusPacket},
+};
+
+/// A monotonic clock where instants are the duration since an arbitrary epoch.
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+/// A request from the collector to the meter.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    SndUd { ci: u8, data: Vec<u8> },
+    ReqUd2,
+}
+
+/// The outcome of a request.
+pub enum Reply {
+    Ack(Request),
+    Nack(Request),
+    RspUd(Request, WMBusPacket),
+    /// The meter did not reply within the configured number of attempts.
+    Timeout(Request),
+}
+
+/// A frame that must be transmitted by the collector with a start between earliest and latest.
+pub struct Transmission {
+    pub packet: WMBusPacket,
+    pub earliest: Duration,
+    pub latest: Duration,
+}
+
+pub struct SessionConfig {
+    /// The mode of the meter, which gives the response delay.
+    pub mode: Mode,
+    /// The time allowed for the reply after the latest transmission start.
+    pub reply_timeout: Duration,
+    /// The number of access opportunities used for a request before it times out.
+    pub max_attempts: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    /// Waiting for an access opportunity from the meter.
+    Idle,
+    /// A request is transmitted and the reply must be received before the deadline.
+    AwaitingReply { deadline: Duration },
+}
+
+/// The collector side of the link layer session with a single bidirectional meter.
+pub struct Session<C: Clock> {
+    clock: C,
+    meter: MBusAddress,
+    config: SessionConfig,
+    state: State,
+    fcb: bool,
+    attempts: u8,
+    requests: VecDeque<Request>,
+    replies: VecDeque<Reply>,
+}
+
+impl<C: Clock> Session<C> {
+    pub fn new(clock: C, meter: MBusAddress, config: SessionConfig) -> Self {
+        Self {
+            clock,
+            meter,
+            config,
+            state: State::Idle,
+            // The first frame after a link reset has the frame count bit set
+            fcb: true,
+            attempts: 0,
+            requests: VecDeque::new(),
+            replies: VecDeque::new(),
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Queue a request to be transmitted at the next access opportunity.
+    pub fn enqueue(&mut self, request: Request) {
+        self.requests.push_back(request);
+    }
+
+    /// Take the next reply from the meter.
+    pub fn reply(&mut self) -> Option<Reply> {
+        self.replies.pop_front()
+    }
+
+    /// Handle a packet received from the meter, where `frame_size` is the number of bytes following the syncword
+    /// and `received_at` is the instant of the syncword detection as for `Mode::response_window()`.
+    /// A transmission is returned if the collector should respond to the packet.
+    pub fn receive(&mut self, packet: &WMBusPacket, frame_size: usize, received_at: Duration) -> Option<Transmission> {
+        if packet.link_layer.address != self.meter {
+            return None;
+        }
+
+        match packet.link_layer.function_code()? {
+            FunctionCode::SndNr | FunctionCode::AccNr | FunctionCode::AccDmd => {
+                if let State::AwaitingReply { .. } = self.state {
+                    // The meter did not receive the request, or its reply was lost
+                    self.fail_attempt();
+                }
+                self.transmit(packet, frame_size, received_at)
+            }
+            function_code @ (FunctionCode::Ack | FunctionCode::Nack | FunctionCode::RspUd) => {
+                if self.state == State::Idle {
+                    return None;
+                }
+                let expected = matches!(
+                    (function_code, self.requests.front()?),
+                    (FunctionCode::Ack | FunctionCode::Nack, Request::SndUd { .. })
+                        | (FunctionCode::RspUd, Request::ReqUd2)
+                );
+                if !expected {
+                    return None;
+                }
+
+                let request = self.requests.pop_front().unwrap();
+                self.finish(match function_code {
+                    FunctionCode::Ack => Reply::Ack(request),
+                    FunctionCode::Nack => Reply::Nack(request),
+                    _ => Reply::RspUd(request, packet.clone()),
+                });
+
+                // The meter may remain accessible for the next request
+                self.transmit(packet, frame_size, received_at)
+            }
+            _ => None,
+        }
+    }
+
+    /// Handle reply timeouts, must be called regularly while awaiting a reply.
+    pub fn poll(&mut self) {
+        if let State::AwaitingReply { deadline } = self.state {
+            if self.clock.now() > deadline {
+                self.fail_attempt();
+            }
+        }
+    }
+
+    fn transmit(&mut self, packet: &WMBusPacket, frame_size: usize, received_at: Duration) -> Option<Transmission> {
+        let ell = packet.ext_link_layer.as_ref()?;
+        if !ell.communication_control().accessibility() {
+            return None;
+        }
+
+        let (function_code, application_layer) = match self.requests.front()? {
+            Request::SndUd { ci, data } => (
+                FunctionCode::SndUd,
+                Some(ApplicationLayer {
+                    ci: *ci,
+                    data: data.clone(),
+                }),
+            ),
+            Request::ReqUd2 => (FunctionCode::ReqUd2, None),
+        };
+        let mut control = function_code as u8 | LinkLayer::FCV;
+        if self.fcb {
+            control |= LinkLayer::FCB;
+        }
+
+        let window = self
+            .config
+            .mode
+            .response_window(frame_size, Some(ell.communication_control()), received_at);
+        self.state = State::AwaitingReply {
+            deadline: window.latest + self.config.reply_timeout,
+        };
+
+        Some(Transmission {
+            packet: WMBusPacket {
+                application_layer,
+                authentication_layer: None,
+                ext_link_layer: Some(ExtendedLinkLayer::Short {
+                    cc: CommunicationControl::BIDIRECTIONAL,
+                    acc: ell.acc(),
+                }),
+                link_layer: LinkLayer {
+                    length: None,
+                    control,
+                    address: self.meter,
+                },
+            },
+            earliest: window.earliest,
+            latest: window.latest,
+        })
+    }
+
+    fn fail_attempt(&mut self) {
+        self.state = State::Idle;
+        self.attempts += 1;
+        if self.attempts >= self.config.max_attempts {
+            if let Some(request) = self.requests.pop_front() {
+                self.finish(Reply::Timeout(request));
+            }
+        }
+    }
+
+    fn finish(&mut self, reply: Reply) {
+        // The frame count bit is toggled for each new request
+        self.fcb = !self.fcb;
+        self.attempts = 0;
+        self.state = State::Idle;
+        self.replies.push_back(reply);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use core::cell::Cell;
+
+    use crate::mbusaddress::{DeviceType, ManufacturerCode};
+
+    use super::*;
+
+    struct MockClock(Cell<Duration>);
+
+    impl Clock for &MockClock {
+        fn now(&self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    fn meter() -> MBusAddress {
+        MBusAddress::new(ManufacturerCode::KAM, 12345678, 0x01, DeviceType::Water)
+    }
+
+    fn config() -> SessionConfig {
+        SessionConfig {
+            mode: Mode::T,
+            reply_timeout: Duration::from_millis(50),
+            max_attempts: 2,
+        }
+    }
+
+    fn packet(control: u8, cc: u8, acc: u8) -> WMBusPacket {
+        WMBusPacket {
+            application_layer: None,
+            authentication_layer: None,
+            ext_link_layer: Some(ExtendedLinkLayer::Short { cc, acc }),
+            link_layer: LinkLayer {
+                length: None,
+                control,
+                address: meter(),
+            },
+        }
+    }
+
+    /// The frame size with a time on air of 3 ms in mode T and 2 ms in mode C.
+    const FRAME_SIZE: usize = 25;
+
+    const ACCESSIBLE: u8 = CommunicationControl::BIDIRECTIONAL | CommunicationControl::ACCESSIBILITY;
+
+    #[test]
+    pub fn responds_to_accessible_meter() {
+        let clock = MockClock(Cell::new(Duration::ZERO));
+        let mut session = Session::new(&clock, meter(), config());
+        session.enqueue(Request::SndUd {
+            ci: 0x5A,
+            data: vec![0x01, 0x02],
+        });
+
+        // Not accessible
+        assert!(session
+            .receive(&packet(0x44, CommunicationControl::BIDIRECTIONAL, 0x10), FRAME_SIZE, Duration::from_millis(100))
+            .is_none());
+
+        let transmission = session
+            .receive(&packet(0x44, ACCESSIBLE, 0x11), FRAME_SIZE, Duration::from_millis(200))
+            .unwrap();
+        assert_eq!(Duration::from_millis(205), transmission.earliest);
+        assert_eq!(Duration::from_millis(206), transmission.latest);
+        assert_eq!(0x73, transmission.packet.link_layer.control);
+        assert_eq!(0x11, transmission.packet.ext_link_layer.unwrap().acc());
+        assert_eq!(vec![0x01, 0x02], transmission.packet.application_layer.unwrap().data);
+        assert_eq!(
+            State::AwaitingReply {
+                deadline: Duration::from_millis(256)
+            },
+            session.state()
+        );
+
+        assert!(session.receive(&packet(0x00, 0, 0x11), FRAME_SIZE, Duration::from_millis(210)).is_none());
+        assert!(matches!(session.reply(), Some(Reply::Ack(Request::SndUd { ci: 0x5A, .. }))));
+        assert_eq!(State::Idle, session.state());
+    }
+
+    #[test]
+    pub fn responds_within_mode_response_delay() {
+        let clock = MockClock(Cell::new(Duration::ZERO));
+        let config = SessionConfig {
+            mode: Mode::C,
+            ..config()
+        };
+        let mut session = Session::new(&clock, meter(), config);
+        session.enqueue(Request::ReqUd2);
+        session.enqueue(Request::ReqUd2);
+
+        // The slow response delay
+        let transmission = session
+            .receive(&packet(0x44, ACCESSIBLE, 0x01), FRAME_SIZE, Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(Duration::from_micros(11_001_500), transmission.earliest);
+        assert_eq!(Duration::from_micros(11_002_500), transmission.latest);
+        let window = Mode::C.response_window(
+            FRAME_SIZE,
+            Some(CommunicationControl(ACCESSIBLE)),
+            Duration::from_secs(10),
+        );
+        assert_eq!((window.earliest, window.latest), (transmission.earliest, transmission.latest));
+
+        // The fast response delay requested in the CC field
+        let fast = ACCESSIBLE | CommunicationControl::RESPONSE_DELAY;
+        let transmission = session
+            .receive(&packet(0x08, fast, 0x01), FRAME_SIZE, Duration::from_secs(12))
+            .unwrap();
+        assert_eq!(Duration::from_micros(12_101_500), transmission.earliest);
+        assert_eq!(Duration::from_micros(12_102_500), transmission.latest);
+    }
+
+    #[test]
+    pub fn toggles_fcb_and_continues_with_next_request() {
+        let clock = MockClock(Cell::new(Duration::ZERO));
+        let mut session = Session::new(&clock, meter(), config());
+        session.enqueue(Request::SndUd {
+            ci: 0x5A,
+            data: vec![],
+        });
+        session.enqueue(Request::ReqUd2);
+
+        let transmission = session
+            .receive(&packet(0x47, ACCESSIBLE, 0x20), FRAME_SIZE, Duration::from_millis(0))
+            .unwrap();
+        assert_eq!(0x73, transmission.packet.link_layer.control);
+
+        let transmission = session
+            .receive(&packet(0x00, ACCESSIBLE, 0x20), FRAME_SIZE, Duration::from_millis(10))
+            .unwrap();
+        assert_eq!(0x5B, transmission.packet.link_layer.control);
+        assert!(transmission.packet.application_layer.is_none());
+
+        assert!(session.receive(&packet(0x08, 0, 0x20), FRAME_SIZE, Duration::from_millis(20)).is_none());
+        assert!(matches!(session.reply(), Some(Reply::Ack(_))));
+        assert!(matches!(session.reply(), Some(Reply::RspUd(Request::ReqUd2, _))));
+        assert!(session.reply().is_none());
+    }
+
+    #[test]
+    pub fn retries_with_same_fcb_until_timeout() {
+        let clock = MockClock(Cell::new(Duration::ZERO));
+        let mut session = Session::new(&clock, meter(), config());
+        session.enqueue(Request::ReqUd2);
+
+        let transmission = session
+            .receive(&packet(0x44, ACCESSIBLE, 0x01), FRAME_SIZE, Duration::from_millis(0))
+            .unwrap();
+        assert_eq!(0x7B, transmission.packet.link_layer.control);
+
+        clock.0.set(Duration::from_millis(56));
+        session.poll();
+        assert!(matches!(session.state(), State::AwaitingReply { .. }));
+        clock.0.set(Duration::from_millis(57));
+        session.poll();
+        assert_eq!(State::Idle, session.state());
+        assert!(session.reply().is_none());
+
+        // Second and last attempt
+        let transmission = session
+            .receive(&packet(0x44, ACCESSIBLE, 0x02), FRAME_SIZE, Duration::from_millis(1000))
+            .unwrap();
+        assert_eq!(0x7B, transmission.packet.link_layer.control);
+        assert!(session
+            .receive(&packet(0x44, ACCESSIBLE, 0x03), FRAME_SIZE, Duration::from_millis(2000))
+            .is_none());
+        assert!(matches!(session.reply(), Some(Reply::Timeout(Request::ReqUd2))));
+
+        // The next request has a toggled frame count bit
+        session.enqueue(Request::ReqUd2);
+        let transmission = session
+            .receive(&packet(0x44, ACCESSIBLE, 0x04), FRAME_SIZE, Duration::from_millis(3000))
+            .unwrap();
+        assert_eq!(0x5B, transmission.packet.link_layer.control);
+    }
+
+    #[test]
+    pub fn ignores_other_meters() {
+        let clock = MockClock(Cell::new(Duration::ZERO));
+        let mut session = Session::new(&clock, meter(), config());
+        session.enqueue(Request::ReqUd2);
+
+        let mut other = packet(0x44, ACCESSIBLE, 0x01);
+        other.link_layer.address = MBusAddress::new(ManufacturerCode::KAM, 87654321, 0x01, DeviceType::Water);
+        assert!(session.receive(&other, FRAME_SIZE, Duration::from_millis(0)).is_none());
+        assert_eq!(State::Idle, session.state());
+    }
+}
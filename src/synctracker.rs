@@ -0,0 +1,188 @@
+use core::time::Duration;
+
+use crate::{mbusaddress::MBusAddress, wmbus::WMBusPacket};
+
+/// The window where the next synchronous transmission is expected.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransmissionWindow {
+    pub earliest: Duration,
+    pub latest: Duration,
+}
+
+/// Tracker for a meter that transmits synchronously, i.e. with the S bit in the CC field.
+/// The access number is incremented for each transmission, so the nominal interval
+/// can be learned even if some transmissions are missed.
+pub struct SyncTracker {
+    meter: MBusAddress,
+    jitter: Duration,
+    last: Option<(Duration, u8)>,
+    interval: Option<Duration>,
+}
+
+impl SyncTracker {
+    /// Create a tracker for the meter where jitter is the maximum deviation from the nominal transmission instant.
+    pub fn new(meter: MBusAddress, jitter: Duration) -> Self {
+        Self {
+            meter,
+            jitter,
+            last: None,
+            interval: None,
+        }
+    }
+
+    /// Get the learned nominal transmission interval.
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    pub fn meter(&self) -> &MBusAddress {
+        &self.meter
+    }
+
+    /// Update the tracker with a packet received at the instant received_at.
+    /// Packets from other meters and packets that are not synchronous are ignored.
+    pub fn update(&mut self, packet: &WMBusPacket, received_at: Duration) {
+        if packet.link_layer.address != self.meter {
+            return;
+        }
+        let ell = match &packet.ext_link_layer {
+            Some(ell) if ell.communication_control().synchronized() => ell,
+            _ => return,
+        };
+        let acc = ell.acc();
+
+        if let Some((last_received_at, last_acc)) = self.last {
+            let transmissions = acc.wrapping_sub(last_acc) as u32;
+            if transmissions == 0 || received_at <= last_received_at {
+                // Duplicate or out of order
+                return;
+            }
+
+            let interval = (received_at - last_received_at) / transmissions;
+            self.interval = Some(match self.interval {
+                // Only adjust slowly to the new estimate as it includes the jitter
+                Some(current) if interval <= current * 2 && current <= interval * 2 => {
+                    (current * 7 + interval) / 8
+                }
+                _ => interval,
+            });
+        }
+
+        self.last = Some((received_at, acc));
+    }
+
+    /// Predict the window for the next transmission after the instant now.
+    pub fn next_window(&self, now: Duration) -> Option<TransmissionWindow> {
+        let (last_received_at, _) = self.last?;
+        let interval = self.interval?;
+        if interval.is_zero() {
+            return None;
+        }
+
+        // Skip the transmissions where the window has passed
+        let elapsed = now.saturating_sub(last_received_at + self.jitter);
+        let missed = (elapsed.as_nanos() / interval.as_nanos()) as u32;
+        let nominal = last_received_at + interval * (missed + 1);
+
+        Some(TransmissionWindow {
+            earliest: nominal.saturating_sub(self.jitter),
+            latest: nominal + self.jitter,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        mbusaddress::{DeviceType, MBusAddress, ManufacturerCode},
+        wmbus::{CommunicationControl, ExtendedLinkLayer, LinkLayer},
+    };
+
+    use super::*;
+
+    fn meter(serial_number: u32) -> MBusAddress {
+        MBusAddress::new(ManufacturerCode::KAM, serial_number, 0x01, DeviceType::Water)
+    }
+
+    fn packet(cc: u8, acc: u8) -> WMBusPacket {
+        packet_from(meter(12345678), cc, acc)
+    }
+
+    fn packet_from(address: MBusAddress, cc: u8, acc: u8) -> WMBusPacket {
+        WMBusPacket {
+            application_layer: None,
+            authentication_layer: None,
+            ext_link_layer: Some(ExtendedLinkLayer::Short { cc, acc }),
+            link_layer: LinkLayer {
+                length: None,
+                control: 0x44,
+                address,
+            },
+        }
+    }
+
+    const SYNCHRONIZED: u8 = CommunicationControl::SYNCHRONIZED;
+
+    #[test]
+    pub fn learns_interval() {
+        let mut tracker = SyncTracker::new(meter(12345678), Duration::from_millis(500));
+        tracker.update(&packet(SYNCHRONIZED, 0x01), Duration::from_secs(100));
+        assert!(tracker.next_window(Duration::from_secs(100)).is_none());
+
+        tracker.update(&packet(SYNCHRONIZED, 0x02), Duration::from_secs(116));
+        assert_eq!(Some(Duration::from_secs(16)), tracker.interval());
+        assert_eq!(
+            Some(TransmissionWindow {
+                earliest: Duration::from_millis(131_500),
+                latest: Duration::from_millis(132_500),
+            }),
+            tracker.next_window(Duration::from_secs(117))
+        );
+    }
+
+    #[test]
+    pub fn learns_interval_with_missed_transmissions() {
+        let mut tracker = SyncTracker::new(meter(12345678), Duration::from_millis(500));
+        tracker.update(&packet(SYNCHRONIZED, 0xFE), Duration::from_secs(100));
+        tracker.update(&packet(SYNCHRONIZED, 0x01), Duration::from_secs(148));
+        assert_eq!(Some(Duration::from_secs(16)), tracker.interval());
+
+        // The window for the transmission at 164s has passed
+        assert_eq!(
+            Some(TransmissionWindow {
+                earliest: Duration::from_millis(179_500),
+                latest: Duration::from_millis(180_500),
+            }),
+            tracker.next_window(Duration::from_secs(165))
+        );
+    }
+
+    #[test]
+    pub fn smooths_jitter() {
+        let mut tracker = SyncTracker::new(meter(12345678), Duration::from_millis(500));
+        tracker.update(&packet(SYNCHRONIZED, 0x01), Duration::from_secs(100));
+        tracker.update(&packet(SYNCHRONIZED, 0x02), Duration::from_secs(116));
+        tracker.update(&packet(SYNCHRONIZED, 0x03), Duration::from_secs(132) + Duration::from_millis(400));
+        assert_eq!(Some(Duration::from_millis(16_050)), tracker.interval());
+    }
+
+    #[test]
+    pub fn ignores_asynchronous_and_duplicate_packets() {
+        let mut tracker = SyncTracker::new(meter(12345678), Duration::from_millis(500));
+        tracker.update(&packet(SYNCHRONIZED, 0x01), Duration::from_secs(100));
+        tracker.update(&packet(0x00, 0x02), Duration::from_secs(110));
+        tracker.update(&packet(SYNCHRONIZED, 0x01), Duration::from_secs(101));
+        assert!(tracker.interval().is_none());
+    }
+
+    #[test]
+    pub fn ignores_other_meters() {
+        let mut tracker = SyncTracker::new(meter(12345678), Duration::from_millis(500));
+        let other = meter(87654321);
+        tracker.update(&packet(SYNCHRONIZED, 0x01), Duration::from_secs(100));
+        tracker.update(&packet_from(other, SYNCHRONIZED, 0x41), Duration::from_secs(105));
+        tracker.update(&packet_from(other, SYNCHRONIZED, 0x42), Duration::from_secs(125));
+        tracker.update(&packet(SYNCHRONIZED, 0x02), Duration::from_secs(116));
+        assert_eq!(Some(Duration::from_secs(16)), tracker.interval());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{
+    cifield::{CiField, TransportHeader},
+    configurationfield::ConfigurationField,
+    mbusaddress::MBusAddress,
+};
+
+/// The application status of the status field according to EN13757-7.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive)]
+#[repr(u8)]
+pub enum ApplicationStatus {
+    NoError = 0,
+    Busy = 1,
+    Error = 2,
+    /// Abnormal condition or alarm.
+    Alarm = 3,
+}
+
+/// The status field of the transport layer header.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StatusField(pub u8);
+
+impl StatusField {
+    pub const APPLICATION_STATUS: u8 = 0x03;
+    pub const POWER_LOW: u8 = 0x04;
+    pub const PERMANENT_ERROR: u8 = 0x08;
+    pub const TEMPORARY_ERROR: u8 = 0x10;
+    pub const MANUFACTURER_SPECIFIC: u8 = 0xE0;
+
+    pub fn application_status(self) -> ApplicationStatus {
+        num_traits::FromPrimitive::from_u8(self.0 & Self::APPLICATION_STATUS).unwrap()
+    }
+
+    pub fn power_low(self) -> bool {
+        self.0 & Self::POWER_LOW != 0
+    }
+
+    pub fn permanent_error(self) -> bool {
+        self.0 & Self::PERMANENT_ERROR != 0
+    }
+
+    pub fn temporary_error(self) -> bool {
+        self.0 & Self::TEMPORARY_ERROR != 0
+    }
+
+    /// Get the three manufacturer specific bits.
+    pub fn manufacturer_specific(self) -> u8 {
+        (self.0 & Self::MANUFACTURER_SPECIFIC) >> 5
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransportLayer {
+    Short {
+        acc: u8,
+        status: u8,
+        configuration: ConfigurationField,
+    },
+    Long {
+        address: MBusAddress,
+        acc: u8,
+        status: u8,
+        configuration: ConfigurationField,
+    },
+}
+
+impl TransportLayer {
+    /// Parse the transport layer header implied by the CI field from the data following it.
+    pub fn parse(ci: CiField, data: &[u8]) -> Result<Option<TransportLayer>, ()> {
+        let tpl = match ci.header() {
+            Some(TransportHeader::Short) => {
+                if data.len() < 4 {
+                    return Err(());
+                }
+                Some(TransportLayer::Short {
+                    acc: data[0],
+                    status: data[1],
+                    configuration: ConfigurationField::parse(&data[2..])?,
+                })
+            }
+            Some(TransportHeader::Long) => {
+                if data.len() < 12 {
+                    return Err(());
+                }
+                // The identification number precedes the manufacturer in the long header
+                let identifier = [
+                    data[4], data[5], data[0], data[1], data[2], data[3], data[6], data[7],
+                ];
+                Some(TransportLayer::Long {
+                    address: MBusAddress::parse(identifier)?,
+                    acc: data[8],
+                    status: data[9],
+                    configuration: ConfigurationField::parse(&data[10..])?,
+                })
+            }
+            _ => None,
+        };
+        Ok(tpl)
+    }
+
+    pub fn size(&self) -> usize {
+        match *self {
+            TransportLayer::Short { configuration, .. } => 2 + configuration.size(),
+            TransportLayer::Long { configuration, .. } => 8 + 2 + configuration.size(),
+        }
+    }
+
+    pub fn acc(&self) -> u8 {
+        match *self {
+            TransportLayer::Short { acc, .. } => acc,
+            TransportLayer::Long { acc, .. } => acc,
+        }
+    }
+
+    pub fn status(&self) -> u8 {
+        match *self {
+            TransportLayer::Short { status, .. } => status,
+            TransportLayer::Long { status, .. } => status,
+        }
+    }
+
+    pub fn status_field(&self) -> StatusField {
+        StatusField(self.status())
+    }
+
+    pub fn configuration(&self) -> ConfigurationField {
+        match *self {
+            TransportLayer::Short { configuration, .. } => configuration,
+            TransportLayer::Long { configuration, .. } => configuration,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        configurationfield::SecurityMode,
+        mbusaddress::{DeviceType, ManufacturerCode},
+    };
+
+    use super::*;
+
+    #[test]
+    pub fn parse_short() {
+        let tpl = TransportLayer::parse(CiField::from(0x7A), &[0xA6, 0x10, 0x40, 0x25, 0x6D])
+            .unwrap()
+            .unwrap();
+        assert_eq!(0xA6, tpl.acc());
+        assert_eq!(0x10, tpl.status());
+        assert_eq!(Some(SecurityMode::Mode5), tpl.configuration().security_mode());
+        assert_eq!(4, tpl.configuration().encrypted_blocks);
+        assert_eq!(4, tpl.size());
+    }
+
+    #[test]
+    pub fn parse_long() {
+        let tpl = TransportLayer::parse(
+            CiField::from(0x72),
+            &[0x78, 0x56, 0x34, 0x12, 0x2D, 0x2C, 0x01, 0x07, 0x20, 0x00, 0x00, 0x05],
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            TransportLayer::Long {
+                address: MBusAddress::new(ManufacturerCode::KAM, 12345678, 0x01, DeviceType::Water),
+                acc: 0x20,
+                status: 0x00,
+                configuration: ConfigurationField::parse(&[0x00, 0x05]).unwrap(),
+            },
+            tpl
+        );
+        assert_eq!(12, tpl.size());
+    }
+
+    #[test]
+    pub fn parse_mode7_extension() {
+        let tpl = TransportLayer::parse(CiField::from(0x7A), &[0x01, 0x00, 0x20, 0x07, 0x10, 0x2F])
+            .unwrap()
+            .unwrap();
+        assert_eq!(Some(SecurityMode::Mode7), tpl.configuration().security_mode());
+        assert_eq!(1, tpl.configuration().extension.unwrap().kdf());
+        assert_eq!(5, tpl.size());
+    }
+
+    #[test]
+    pub fn parse_status() {
+        let tpl = TransportLayer::parse(CiField::from(0x7A), &[0x01, 0xAD, 0x00, 0x00])
+            .unwrap()
+            .unwrap();
+        let status = tpl.status_field();
+        assert_eq!(ApplicationStatus::Busy, status.application_status());
+        assert!(status.power_low());
+        assert!(status.permanent_error());
+        assert!(!status.temporary_error());
+        assert_eq!(0x05, status.manufacturer_specific());
+
+        let status = StatusField(0x13);
+        assert_eq!(ApplicationStatus::Alarm, status.application_status());
+        assert!(!status.power_low());
+        assert!(!status.permanent_error());
+        assert!(status.temporary_error());
+        assert_eq!(0, status.manufacturer_specific());
+    }
+
+    #[test]
+    pub fn parse_without_header() {
+        assert_eq!(None, TransportLayer::parse(CiField::from(0x78), &[]).unwrap());
+        assert_eq!(None, TransportLayer::parse(CiField::from(0xA0), &[]).unwrap());
+    }
+
+    #[test]
+    pub fn parse_truncated() {
+        assert!(TransportLayer::parse(CiField::from(0x7A), &[0xA6, 0x10, 0x40]).is_err());
+        assert!(TransportLayer::parse(CiField::from(0x72), &[0x78, 0x56, 0x34, 0x12]).is_err());
+    }
+}
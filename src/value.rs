@@ -0,0 +1,319 @@
+use alloc::{string::String, vec::Vec};
+use core::convert::TryInto;
+
+use crate::{
+    bcd::BcdNumber,
+    datarecord::{lvar_size, DataField},
+    datetime::{Date, DateTime, DaylightSaving, Time},
+};
+
+/// The value of a data record.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// No data, e.g. for selection for readout.
+    None,
+    /// Signed binary integer of 8 to 64 bits.
+    Integer(i64),
+    Real(f32),
+    /// BCD of 2 to 18 digits, where negative values are coded with the most significant nibble 0xF
+    /// or with the LVAR for negative BCD.
+    Bcd(i64),
+    /// Variable length text in reading order.
+    Text(String),
+    /// Variable length binary number in transmitted order.
+    Binary(Vec<u8>),
+    /// Date of type G.
+    Date(Date),
+    /// Time of type J.
+    Time(Time),
+    /// Date and time of type F, I or M.
+    DateTime(DateTime),
+    /// Daylight saving of type K.
+    DaylightSaving(DaylightSaving),
+}
+
+impl Value {
+    /// Decode the data of a record with the data field, where the data of variable length data starts with the LVAR byte.
+    /// Dates and times are decoded as integers and binary numbers, as their data type depends on the VIF.
+    pub fn decode(data_field: DataField, data: &[u8]) -> Result<Value, ()> {
+        if let Some(size) = data_field.size() {
+            if data.len() != size {
+                return Err(());
+            }
+        }
+
+        match data_field {
+            DataField::NoData | DataField::SelectionForReadout => Ok(Value::None),
+            DataField::Integer8
+            | DataField::Integer16
+            | DataField::Integer24
+            | DataField::Integer32
+            | DataField::Integer48
+            | DataField::Integer64 => Ok(Value::Integer(decode_integer(data))),
+            DataField::Real32 => Ok(Value::Real(f32::from_le_bytes(data.try_into().unwrap()))),
+            DataField::Bcd2 | DataField::Bcd4 | DataField::Bcd6 | DataField::Bcd8 | DataField::Bcd12 => {
+                let mut data = data.to_vec();
+                let msb = data.last_mut().unwrap();
+                let negative = *msb & 0xF0 == 0xF0;
+                if negative {
+                    *msb &= 0x0F;
+                }
+                let value = decode_bcd(&data)?;
+                Ok(Value::Bcd(if negative { -value } else { value }))
+            }
+            DataField::VariableLength => {
+                let (&lvar, value) = data.split_first().ok_or(())?;
+                if value.len() != lvar_size(lvar)? {
+                    return Err(());
+                }
+                match lvar {
+                    0x00..=0xBF => Ok(Value::Text(value.iter().rev().map(|&x| x as char).collect())),
+                    0xC0..=0xC9 => Ok(Value::Bcd(decode_bcd(value)?)),
+                    0xD0..=0xD9 => Ok(Value::Bcd(-decode_bcd(value)?)),
+                    _ => Ok(Value::Binary(value.to_vec())),
+                }
+            }
+            DataField::SpecialFunctions => Err(()),
+        }
+    }
+
+    /// Encode the value with the smallest data field, where the data of variable length data starts with the LVAR byte.
+    /// Dates and times are encoded as type G, J, F and K, or as type I when the seconds, day of week or week are present.
+    pub fn encode(&self) -> Result<(DataField, Vec<u8>), ()> {
+        match self {
+            Value::None => Ok((DataField::NoData, Vec::new())),
+            &Value::Integer(value) => {
+                let size = [1, 2, 3, 4, 6, 8]
+                    .iter()
+                    .copied()
+                    .find(|&size| decode_integer(&value.to_le_bytes()[..size]) == value)
+                    .unwrap();
+                let data_field = match size {
+                    1 => DataField::Integer8,
+                    2 => DataField::Integer16,
+                    3 => DataField::Integer24,
+                    4 => DataField::Integer32,
+                    6 => DataField::Integer48,
+                    _ => DataField::Integer64,
+                };
+                Ok((data_field, value.to_le_bytes()[..size].to_vec()))
+            }
+            Value::Real(value) => Ok((DataField::Real32, value.to_le_bytes().to_vec())),
+            &Value::Bcd(value) => {
+                let magnitude = value.unsigned_abs();
+                let mut digits = 1;
+                while digits < 20 && magnitude >= 10u64.pow(digits) {
+                    digits += 1;
+                }
+                // The minus sign takes the most significant nibble of fixed size BCD
+                let sign_digits = digits + (value < 0) as u32;
+                let size = (sign_digits / 2 + sign_digits % 2) as usize;
+                let data_field = match size {
+                    1 => DataField::Bcd2,
+                    2 => DataField::Bcd4,
+                    3 => DataField::Bcd6,
+                    4 => DataField::Bcd8,
+                    5..=6 => DataField::Bcd12,
+                    _ => {
+                        let size = (digits / 2 + digits % 2) as usize;
+                        if size > 9 {
+                            return Err(());
+                        }
+                        let lvar = if value < 0 { 0xD0 } else { 0xC0 } + size as u8;
+                        let mut data = vec![lvar];
+                        data.extend_from_slice(&encode_bcd(magnitude, size)?);
+                        return Ok((DataField::VariableLength, data));
+                    }
+                };
+                let mut data = encode_bcd(magnitude, data_field.size().unwrap())?;
+                if value < 0 {
+                    *data.last_mut().unwrap() |= 0xF0;
+                }
+                Ok((data_field, data))
+            }
+            Value::Text(text) => {
+                let mut data = vec![0];
+                for x in text.chars().rev() {
+                    data.push((x as u32).try_into().map_err(|_| ())?);
+                }
+                if data.len() - 1 > 0xBF {
+                    return Err(());
+                }
+                data[0] = (data.len() - 1) as u8;
+                Ok((DataField::VariableLength, data))
+            }
+            Value::Binary(value) => {
+                let lvar = match value.len() {
+                    size @ 0..=15 => 0xE0 + size as u8,
+                    size @ (16 | 20 | 24 | 28 | 32) => 0xEC + (size / 4) as u8,
+                    48 => 0xF5,
+                    64 => 0xF6,
+                    _ => return Err(()),
+                };
+                let mut data = vec![lvar];
+                data.extend_from_slice(value);
+                Ok((DataField::VariableLength, data))
+            }
+            Value::Date(date) => Ok((DataField::Integer16, date.to_type_g()?.to_vec())),
+            Value::Time(time) => Ok((DataField::Integer24, time.to_type_j()?.to_vec())),
+            Value::DateTime(date_time) => {
+                if date_time.time.second.is_none() && date_time.day_of_week.is_none() && date_time.week.is_none() {
+                    Ok((DataField::Integer32, date_time.to_type_f()?.to_vec()))
+                } else {
+                    Ok((DataField::Integer48, date_time.to_type_i()?.to_vec()))
+                }
+            }
+            Value::DaylightSaving(descriptor) => Ok((DataField::Integer32, descriptor.to_type_k()?.to_vec())),
+        }
+    }
+}
+
+/// Decode a little endian two's complement integer of up to 8 bytes.
+fn decode_integer(data: &[u8]) -> i64 {
+    let mut bytes = [0; 8];
+    bytes[..data.len()].copy_from_slice(data);
+    let shift = 64 - 8 * data.len();
+    (i64::from_le_bytes(bytes) << shift) >> shift
+}
+
+/// Decode little endian BCD of up to 18 digits.
+fn decode_bcd(data: &[u8]) -> Result<i64, ()> {
+    if data.len() > 9 {
+        return Err(());
+    }
+    data.iter().rev().try_fold(0, |value, &byte| {
+        let digits = BcdNumber::new_u16(byte as u16)?.decode();
+        Ok(value * 100 + digits as i64)
+    })
+}
+
+/// Encode little endian BCD of the size in bytes.
+fn encode_bcd(mut value: u64, size: usize) -> Result<Vec<u8>, ()> {
+    let mut data = Vec::with_capacity(size);
+    for _ in 0..size {
+        data.push(BcdNumber::encode_u16((value % 100) as u16)?.bcd_value() as u8);
+        value /= 100;
+    }
+    if value != 0 {
+        return Err(());
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn decode_integer() {
+        assert_eq!(Ok(Value::Integer(-1)), Value::decode(DataField::Integer8, &[0xFF]));
+        assert_eq!(Ok(Value::Integer(12345)), Value::decode(DataField::Integer32, &[0x39, 0x30, 0x00, 0x00]));
+        assert_eq!(Ok(Value::Integer(-2)), Value::decode(DataField::Integer24, &[0xFE, 0xFF, 0xFF]));
+        assert_eq!(
+            Ok(Value::Integer(0x0605_0403_0201)),
+            Value::decode(DataField::Integer48, &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06])
+        );
+        assert_eq!(
+            Ok(Value::Integer(i64::MIN)),
+            Value::decode(DataField::Integer64, &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80])
+        );
+        assert!(Value::decode(DataField::Integer16, &[0x00]).is_err());
+    }
+
+    #[test]
+    pub fn decode_real() {
+        assert_eq!(Ok(Value::Real(1.5)), Value::decode(DataField::Real32, &1.5f32.to_le_bytes()));
+    }
+
+    #[test]
+    pub fn decode_bcd() {
+        assert_eq!(Ok(Value::Bcd(42)), Value::decode(DataField::Bcd2, &[0x42]));
+        assert_eq!(Ok(Value::Bcd(12345678)), Value::decode(DataField::Bcd8, &[0x78, 0x56, 0x34, 0x12]));
+        assert_eq!(
+            Ok(Value::Bcd(123456789012)),
+            Value::decode(DataField::Bcd12, &[0x12, 0x90, 0x78, 0x56, 0x34, 0x12])
+        );
+        // The most significant nibble 0xF is the minus sign
+        assert_eq!(Ok(Value::Bcd(-234)), Value::decode(DataField::Bcd4, &[0x34, 0xF2]));
+        // Other hex digits are errors
+        assert!(Value::decode(DataField::Bcd4, &[0x3A, 0x12]).is_err());
+        assert!(Value::decode(DataField::Bcd4, &[0x34, 0xE2]).is_err());
+    }
+
+    #[test]
+    pub fn decode_variable_length() {
+        assert_eq!(
+            Ok(Value::Text("ABC".into())),
+            Value::decode(DataField::VariableLength, &[0x03, 0x43, 0x42, 0x41])
+        );
+        assert_eq!(
+            Ok(Value::Bcd(123456789012345678)),
+            Value::decode(
+                DataField::VariableLength,
+                &[0xC9, 0x78, 0x56, 0x34, 0x12, 0x90, 0x78, 0x56, 0x34, 0x12]
+            )
+        );
+        assert_eq!(Ok(Value::Bcd(-1234)), Value::decode(DataField::VariableLength, &[0xD2, 0x34, 0x12]));
+        assert_eq!(
+            Ok(Value::Binary(vec![0x01, 0x02])),
+            Value::decode(DataField::VariableLength, &[0xE2, 0x01, 0x02])
+        );
+        assert!(Value::decode(DataField::VariableLength, &[0xF7]).is_err());
+        assert!(Value::decode(DataField::VariableLength, &[]).is_err());
+        assert!(Value::decode(DataField::VariableLength, &[0x03, 0x43, 0x42]).is_err());
+    }
+
+    #[test]
+    pub fn encode_smallest_data_field() {
+        assert_eq!(Ok((DataField::Integer8, vec![0xFF])), Value::Integer(-1).encode());
+        assert_eq!(Ok((DataField::Integer16, vec![0x80, 0x00])), Value::Integer(128).encode());
+        assert_eq!(Ok((DataField::Integer32, vec![0x39, 0x30, 0x80, 0x00])), Value::Integer(0x80_3039).encode());
+        assert_eq!(Ok((DataField::Bcd2, vec![0x42])), Value::Bcd(42).encode());
+        assert_eq!(Ok((DataField::Bcd4, vec![0x34, 0xF2])), Value::Bcd(-234).encode());
+        assert_eq!(Ok((DataField::Bcd4, vec![0x34, 0x12])), Value::Bcd(1234).encode());
+        assert_eq!(Ok((DataField::Bcd6, vec![0x34, 0x12, 0xF0])), Value::Bcd(-1234).encode());
+        assert_eq!(
+            Ok((DataField::VariableLength, vec![0xD7, 0x34, 0x12, 0x90, 0x78, 0x56, 0x34, 0x12])),
+            Value::Bcd(-12345678901234).encode()
+        );
+        assert_eq!(Ok((DataField::VariableLength, vec![0x03, 0x43, 0x42, 0x41])), Value::Text("ABC".into()).encode());
+        assert!(Value::Text("\u{263A}".into()).encode().is_err());
+        assert_eq!(Ok((DataField::VariableLength, vec![0xF0; 17])), Value::Binary(vec![0xF0; 16]).encode());
+        assert!(Value::Binary(vec![0; 17]).encode().is_err());
+    }
+
+    #[test]
+    pub fn encode_round_trip() {
+        let values = [
+            Value::None,
+            Value::Integer(0),
+            Value::Integer(-129),
+            Value::Integer(i64::MAX),
+            Value::Integer(i64::MIN),
+            Value::Integer(0x7FFF_FFFF_FFFF),
+            Value::Real(-0.25),
+            Value::Bcd(0),
+            Value::Bcd(-9),
+            Value::Bcd(999999999999),
+            Value::Bcd(-99999999999),
+            Value::Bcd(-999999999999),
+            Value::Bcd(999999999999999999),
+            Value::Text(String::new()),
+            Value::Text("m3/h".into()),
+            Value::Binary(vec![0x01, 0x02, 0x03]),
+            Value::Binary(vec![0xAA; 64]),
+        ];
+        for value in values.iter() {
+            let (data_field, data) = value.encode().unwrap();
+            assert_eq!(Ok(value.clone()), Value::decode(data_field, &data));
+        }
+        assert!(Value::Bcd(1_000_000_000_000_000_000).encode().is_err());
+    }
+
+    #[test]
+    pub fn decode_no_data() {
+        assert_eq!(Ok(Value::None), Value::decode(DataField::NoData, &[]));
+        assert_eq!(Ok(Value::None), Value::decode(DataField::SelectionForReadout, &[]));
+        assert!(Value::decode(DataField::SpecialFunctions, &[]).is_err());
+    }
+}
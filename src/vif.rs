@@ -0,0 +1,770 @@
+use alloc::vec::Vec;
+
+/// The physical quantity of a value information field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quantity {
+    Energy,
+    Volume,
+    Mass,
+    OnTime,
+    OperatingTime,
+    Power,
+    VolumeFlow,
+    MassFlow,
+    FlowTemperature,
+    ReturnTemperature,
+    TemperatureDifference,
+    ExternalTemperature,
+    Pressure,
+    /// Date of data type G.
+    Date,
+    /// Date and time of data type F, I, J or M.
+    DateTime,
+    /// Units for heat cost allocator.
+    HeatCostAllocator,
+    AveragingDuration,
+    ActualityDuration,
+    FabricationNumber,
+    EnhancedIdentification,
+    BusAddress,
+    /// The unit is the ASCII text of the plain text VIF.
+    PlainText,
+    // First extension table, i.e. VIF 0xFD
+    Credit,
+    Debit,
+    /// Unique message identification, i.e. the access number.
+    AccessNumber,
+    Medium,
+    Manufacturer,
+    ParameterSetIdentification,
+    ModelVersion,
+    HardwareVersion,
+    FirmwareVersion,
+    SoftwareVersion,
+    CustomerLocation,
+    Customer,
+    AccessCodeUser,
+    AccessCodeOperator,
+    AccessCodeSystemOperator,
+    AccessCodeDeveloper,
+    Password,
+    ErrorFlags,
+    ErrorMask,
+    SecurityKey,
+    DigitalOutput,
+    DigitalInput,
+    BaudRate,
+    ResponseDelayTime,
+    Retry,
+    RemoteControl,
+    FirstStorageNumber,
+    LastStorageNumber,
+    StorageBlockSize,
+    TariffSubunitDescriptor,
+    StorageInterval,
+    OperatorSpecificData,
+    TimePointSecond,
+    DurationSinceLastReadout,
+    TariffStart,
+    TariffDuration,
+    TariffPeriod,
+    Dimensionless,
+    DataContainer,
+    TransmissionPeriod,
+    Voltage,
+    Current,
+    ResetCounter,
+    CumulationCounter,
+    ControlSignal,
+    DayOfWeek,
+    WeekNumber,
+    DayChangeTimePoint,
+    ParameterActivationState,
+    SupplierInformation,
+    DurationSinceLastCumulation,
+    BatteryOperatingTime,
+    BatteryChangeDateTime,
+    RfLevel,
+    /// Daylight saving of data type K.
+    DaylightSaving,
+    ListeningWindow,
+    RemainingBatteryLifetime,
+    MeterStopCount,
+    ManufacturerDataContainer,
+    // Second extension table, i.e. VIF 0xFB
+    ReactiveEnergy,
+    ApparentEnergy,
+    ReactivePower,
+    ApparentPower,
+    RelativeHumidity,
+    PhaseVoltageVoltage,
+    PhaseVoltageCurrent,
+    Frequency,
+    TemperatureLimit,
+    CumulativeMaxPower,
+}
+
+/// The unit of a value information field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    /// Dimensionless, or the quantity is not a physical value.
+    None,
+    WattHour,
+    Joule,
+    CubicMetre,
+    Kilogram,
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Watt,
+    JoulePerHour,
+    CubicMetrePerHour,
+    CubicMetrePerMinute,
+    CubicMetrePerSecond,
+    KilogramPerHour,
+    Celsius,
+    Kelvin,
+    Bar,
+    Month,
+    Year,
+    /// The local legal currency.
+    Currency,
+    Baud,
+    BitTime,
+    Volt,
+    Ampere,
+    DecibelMilliwatt,
+    VarHour,
+    VoltAmpereHour,
+    Calorie,
+    Var,
+    VoltAmpere,
+    Percent,
+    CubicFoot,
+    Fahrenheit,
+    Degree,
+    Hertz,
+}
+
+/// The quantity and unit of a value, where the value is multiplied by 10 to the power of the exponent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ValueInformation {
+    pub quantity: Quantity,
+    pub unit: Unit,
+    pub exponent: i8,
+}
+
+impl ValueInformation {
+    pub const fn new(quantity: Quantity, unit: Unit, exponent: i8) -> Self {
+        Self {
+            quantity,
+            unit,
+            exponent,
+        }
+    }
+
+    /// Get the value information of a primary VIF according to EN13757-3,
+    /// or None if the VIF is reserved or selects an extension table.
+    pub fn from_primary(vif: u8) -> Option<ValueInformation> {
+        let n = (vif & 0x07) as i8;
+        let nn = (vif & 0x03) as i8;
+        let (quantity, unit, exponent) = match vif & 0x7F {
+            0x00..=0x07 => (Quantity::Energy, Unit::WattHour, n - 3),
+            0x08..=0x0F => (Quantity::Energy, Unit::Joule, n),
+            0x10..=0x17 => (Quantity::Volume, Unit::CubicMetre, n - 6),
+            0x18..=0x1F => (Quantity::Mass, Unit::Kilogram, n - 3),
+            0x20..=0x23 => (Quantity::OnTime, time_unit(vif), 0),
+            0x24..=0x27 => (Quantity::OperatingTime, time_unit(vif), 0),
+            0x28..=0x2F => (Quantity::Power, Unit::Watt, n - 3),
+            0x30..=0x37 => (Quantity::Power, Unit::JoulePerHour, n),
+            0x38..=0x3F => (Quantity::VolumeFlow, Unit::CubicMetrePerHour, n - 6),
+            0x40..=0x47 => (Quantity::VolumeFlow, Unit::CubicMetrePerMinute, n - 7),
+            0x48..=0x4F => (Quantity::VolumeFlow, Unit::CubicMetrePerSecond, n - 9),
+            0x50..=0x57 => (Quantity::MassFlow, Unit::KilogramPerHour, n - 3),
+            0x58..=0x5B => (Quantity::FlowTemperature, Unit::Celsius, nn - 3),
+            0x5C..=0x5F => (Quantity::ReturnTemperature, Unit::Celsius, nn - 3),
+            0x60..=0x63 => (Quantity::TemperatureDifference, Unit::Kelvin, nn - 3),
+            0x64..=0x67 => (Quantity::ExternalTemperature, Unit::Celsius, nn - 3),
+            0x68..=0x6B => (Quantity::Pressure, Unit::Bar, nn - 3),
+            0x6C => (Quantity::Date, Unit::None, 0),
+            0x6D => (Quantity::DateTime, Unit::None, 0),
+            0x6E => (Quantity::HeatCostAllocator, Unit::None, 0),
+            0x70..=0x73 => (Quantity::AveragingDuration, time_unit(vif), 0),
+            0x74..=0x77 => (Quantity::ActualityDuration, time_unit(vif), 0),
+            0x78 => (Quantity::FabricationNumber, Unit::None, 0),
+            0x79 => (Quantity::EnhancedIdentification, Unit::None, 0),
+            0x7A => (Quantity::BusAddress, Unit::None, 0),
+            0x7C => (Quantity::PlainText, Unit::None, 0),
+            _ => return None,
+        };
+        Some(ValueInformation::new(quantity, unit, exponent))
+    }
+
+    /// Get the value information of a VIF from the first extension table, i.e. following the VIF 0xFD.
+    pub fn from_first_extension(vif: u8) -> Option<ValueInformation> {
+        let nn = (vif & 0x03) as i8;
+        let nnnn = (vif & 0x0F) as i8;
+        let (quantity, unit, exponent) = match vif & 0x7F {
+            0x00..=0x03 => (Quantity::Credit, Unit::Currency, nn - 3),
+            0x04..=0x07 => (Quantity::Debit, Unit::Currency, nn - 3),
+            0x08 => (Quantity::AccessNumber, Unit::None, 0),
+            0x09 => (Quantity::Medium, Unit::None, 0),
+            0x0A => (Quantity::Manufacturer, Unit::None, 0),
+            0x0B => (Quantity::ParameterSetIdentification, Unit::None, 0),
+            0x0C => (Quantity::ModelVersion, Unit::None, 0),
+            0x0D => (Quantity::HardwareVersion, Unit::None, 0),
+            0x0E => (Quantity::FirmwareVersion, Unit::None, 0),
+            0x0F => (Quantity::SoftwareVersion, Unit::None, 0),
+            0x10 => (Quantity::CustomerLocation, Unit::None, 0),
+            0x11 => (Quantity::Customer, Unit::None, 0),
+            0x12 => (Quantity::AccessCodeUser, Unit::None, 0),
+            0x13 => (Quantity::AccessCodeOperator, Unit::None, 0),
+            0x14 => (Quantity::AccessCodeSystemOperator, Unit::None, 0),
+            0x15 => (Quantity::AccessCodeDeveloper, Unit::None, 0),
+            0x16 => (Quantity::Password, Unit::None, 0),
+            0x17 => (Quantity::ErrorFlags, Unit::None, 0),
+            0x18 => (Quantity::ErrorMask, Unit::None, 0),
+            0x19 => (Quantity::SecurityKey, Unit::None, 0),
+            0x1A => (Quantity::DigitalOutput, Unit::None, 0),
+            0x1B => (Quantity::DigitalInput, Unit::None, 0),
+            0x1C => (Quantity::BaudRate, Unit::Baud, 0),
+            0x1D => (Quantity::ResponseDelayTime, Unit::BitTime, 0),
+            0x1E => (Quantity::Retry, Unit::None, 0),
+            0x1F => (Quantity::RemoteControl, Unit::None, 0),
+            0x20 => (Quantity::FirstStorageNumber, Unit::None, 0),
+            0x21 => (Quantity::LastStorageNumber, Unit::None, 0),
+            0x22 => (Quantity::StorageBlockSize, Unit::None, 0),
+            0x23 => (Quantity::TariffSubunitDescriptor, Unit::None, 0),
+            0x24..=0x27 => (Quantity::StorageInterval, time_unit(vif), 0),
+            0x28 => (Quantity::StorageInterval, Unit::Month, 0),
+            0x29 => (Quantity::StorageInterval, Unit::Year, 0),
+            0x2A => (Quantity::OperatorSpecificData, Unit::None, 0),
+            0x2B => (Quantity::TimePointSecond, Unit::Second, 0),
+            0x2C..=0x2F => (Quantity::DurationSinceLastReadout, time_unit(vif), 0),
+            0x30 => (Quantity::TariffStart, Unit::None, 0),
+            0x31..=0x33 => (Quantity::TariffDuration, time_unit(vif), 0),
+            0x34..=0x37 => (Quantity::TariffPeriod, time_unit(vif), 0),
+            0x38 => (Quantity::TariffPeriod, Unit::Month, 0),
+            0x39 => (Quantity::TariffPeriod, Unit::Year, 0),
+            0x3A => (Quantity::Dimensionless, Unit::None, 0),
+            0x3B => (Quantity::DataContainer, Unit::None, 0),
+            0x3C..=0x3F => (Quantity::TransmissionPeriod, time_unit(vif), 0),
+            0x40..=0x4F => (Quantity::Voltage, Unit::Volt, nnnn - 9),
+            0x50..=0x5F => (Quantity::Current, Unit::Ampere, nnnn - 12),
+            0x60 => (Quantity::ResetCounter, Unit::None, 0),
+            0x61 => (Quantity::CumulationCounter, Unit::None, 0),
+            0x62 => (Quantity::ControlSignal, Unit::None, 0),
+            0x63 => (Quantity::DayOfWeek, Unit::None, 0),
+            0x64 => (Quantity::WeekNumber, Unit::None, 0),
+            0x65 => (Quantity::DayChangeTimePoint, Unit::None, 0),
+            0x66 => (Quantity::ParameterActivationState, Unit::None, 0),
+            0x67 => (Quantity::SupplierInformation, Unit::None, 0),
+            0x68..=0x6B => (Quantity::DurationSinceLastCumulation, long_time_unit(vif), 0),
+            0x6C..=0x6F => (Quantity::BatteryOperatingTime, long_time_unit(vif), 0),
+            0x70 => (Quantity::BatteryChangeDateTime, Unit::None, 0),
+            0x71 => (Quantity::RfLevel, Unit::DecibelMilliwatt, 0),
+            0x72 => (Quantity::DaylightSaving, Unit::None, 0),
+            0x73 => (Quantity::ListeningWindow, Unit::None, 0),
+            0x74 => (Quantity::RemainingBatteryLifetime, Unit::Day, 0),
+            0x75 => (Quantity::MeterStopCount, Unit::None, 0),
+            0x76 => (Quantity::ManufacturerDataContainer, Unit::None, 0),
+            _ => return None,
+        };
+        Some(ValueInformation::new(quantity, unit, exponent))
+    }
+
+    /// Get the value information of a VIF from the second extension table, i.e. following the VIF 0xFB.
+    pub fn from_second_extension(vif: u8) -> Option<ValueInformation> {
+        let n = (vif & 0x01) as i8;
+        let nn = (vif & 0x03) as i8;
+        let nnn = (vif & 0x07) as i8;
+        let (quantity, unit, exponent) = match vif & 0x7F {
+            0x00..=0x01 => (Quantity::Energy, Unit::WattHour, n + 5),
+            0x02..=0x03 => (Quantity::ReactiveEnergy, Unit::VarHour, n + 3),
+            0x04..=0x07 => (Quantity::ApparentEnergy, Unit::VoltAmpereHour, nn + 3),
+            0x08..=0x09 => (Quantity::Energy, Unit::Joule, n + 8),
+            0x0C..=0x0F => (Quantity::Energy, Unit::Calorie, nn + 5),
+            0x10..=0x11 => (Quantity::Volume, Unit::CubicMetre, n + 2),
+            0x14..=0x17 => (Quantity::ReactivePower, Unit::Var, nn),
+            0x18..=0x19 => (Quantity::Mass, Unit::Kilogram, n + 5),
+            0x1A..=0x1B => (Quantity::RelativeHumidity, Unit::Percent, n - 1),
+            0x20 => (Quantity::Volume, Unit::CubicFoot, 0),
+            0x21 => (Quantity::Volume, Unit::CubicFoot, -1),
+            0x28..=0x29 => (Quantity::Power, Unit::Watt, n + 5),
+            0x2A => (Quantity::PhaseVoltageVoltage, Unit::Degree, -1),
+            0x2B => (Quantity::PhaseVoltageCurrent, Unit::Degree, -1),
+            0x2C..=0x2F => (Quantity::Frequency, Unit::Hertz, nn - 3),
+            0x30..=0x31 => (Quantity::Power, Unit::JoulePerHour, n + 8),
+            0x34..=0x37 => (Quantity::ApparentPower, Unit::VoltAmpere, nn),
+            0x58..=0x5B => (Quantity::FlowTemperature, Unit::Fahrenheit, nn - 3),
+            0x5C..=0x5F => (Quantity::ReturnTemperature, Unit::Fahrenheit, nn - 3),
+            0x60..=0x63 => (Quantity::TemperatureDifference, Unit::Fahrenheit, nn - 3),
+            0x64..=0x67 => (Quantity::ExternalTemperature, Unit::Fahrenheit, nn - 3),
+            0x70..=0x73 => (Quantity::TemperatureLimit, Unit::Fahrenheit, nn - 3),
+            0x74..=0x77 => (Quantity::TemperatureLimit, Unit::Celsius, nn - 3),
+            0x78..=0x7F => (Quantity::CumulativeMaxPower, Unit::Watt, nnn - 3),
+            _ => return None,
+        };
+        Some(ValueInformation::new(quantity, unit, exponent))
+    }
+
+    /// Get the primary VIF and the VIF of the extension table for VIF 0xFB and 0xFD, preferring the primary table.
+    /// The extension bits are not set.
+    pub fn to_vif(&self) -> Result<(u8, Option<u8>), ()> {
+        if let Some(vif) = (0x00..=0x7A).find(|&vif| Self::from_primary(vif) == Some(*self)) {
+            return Ok((vif, None));
+        }
+        if let Some(vif) = (0x00..=0x7F).find(|&vif| Self::from_first_extension(vif) == Some(*self)) {
+            return Ok((0xFD, Some(vif)));
+        }
+        if let Some(vif) = (0x00..=0x7F).find(|&vif| Self::from_second_extension(vif) == Some(*self)) {
+            return Ok((0xFB, Some(vif)));
+        }
+        Err(())
+    }
+}
+
+/// The record error codes of the combinable VIFE according to EN13757-3.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive)]
+#[repr(u8)]
+pub enum RecordError {
+    None = 0x00,
+    TooManyDife = 0x01,
+    StorageNumberNotImplemented = 0x02,
+    UnitNumberNotImplemented = 0x03,
+    TariffNumberNotImplemented = 0x04,
+    FunctionNotImplemented = 0x05,
+    DataClassNotImplemented = 0x06,
+    DataSizeNotImplemented = 0x07,
+    TooManyVife = 0x0B,
+    IllegalVifGroup = 0x0C,
+    IllegalVifExponent = 0x0D,
+    VifDifMismatch = 0x0E,
+    UnimplementedAction = 0x0F,
+    /// No data available, i.e. undefined value.
+    NoData = 0x15,
+    DataOverflow = 0x16,
+    DataUnderflow = 0x17,
+    DataError = 0x18,
+    PrematureEndOfRecord = 0x1C,
+}
+
+/// A combinable (orthogonal) VIFE modifying the meaning of a record according to EN13757-3.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VifModifier {
+    /// The record error code from the meter, or for the codes up to 0x0F the object action to the meter.
+    Error(u8),
+    AverageValue,
+    InverseCompactProfile,
+    RelativeDeviation,
+    StandardConformDataContent,
+    CompactProfileWithRegisterNumbers,
+    CompactProfile,
+    /// Per second, minute, hour, day, week, month or year.
+    PerTime(Unit),
+    PerMeasurement,
+    IncrementPerInputPulse(u8),
+    IncrementPerOutputPulse(u8),
+    PerLitre,
+    PerCubicMetre,
+    PerKilogram,
+    PerKelvin,
+    PerKilowattHour,
+    PerGigajoule,
+    PerKilowatt,
+    PerKelvinLitre,
+    PerVolt,
+    PerAmpere,
+    MultipliedBySecond,
+    MultipliedBySecondPerVolt,
+    MultipliedBySecondPerAmpere,
+    /// The value is the start date and time of the quantity.
+    StartDateTime,
+    /// The value is uncorrected at metering conditions instead of converted.
+    Uncorrected,
+    /// Accumulation only of positive contributions, i.e. forward flow.
+    PositiveAccumulation,
+    /// Accumulation of the absolute value only of negative contributions, i.e. backward flow.
+    NegativeAccumulation,
+    NonMetricUnits,
+    ValueAtBaseConditions,
+    ObisDeclaration,
+    LimitValue { upper: bool },
+    LimitExceedCount { upper: bool },
+    /// The date and time of the begin or end of the first or last limit exceed.
+    LimitExceedDateTime { upper: bool, last: bool, end: bool },
+    LimitExceedDuration { upper: bool, last: bool, unit: Unit },
+    /// The duration of the first or last occurrence of the quantity.
+    Duration { last: bool, unit: Unit },
+    /// The date and time of the begin or end of the first or last occurrence of the quantity.
+    DateTime { last: bool, end: bool },
+    /// Multiplicative correction factor of 10 to the power of the exponent.
+    MultiplicativeCorrection(i8),
+    /// Additive correction constant of 10 to the power of the exponent in the unit of the VIF.
+    AdditiveCorrection(i8),
+    /// A VIFE from the extension table of combinable VIFEs.
+    Extension(u8),
+    FutureValue,
+    /// The following VIFEs and the data are manufacturer specific.
+    ManufacturerSpecific,
+    Reserved(u8),
+}
+
+impl VifModifier {
+    /// Decode the combinable VIFE chain, where a manufacturer specific VIFE ends the chain.
+    pub fn decode(vife: &[u8]) -> Vec<VifModifier> {
+        let mut modifiers = Vec::new();
+        let mut iter = vife.iter().map(|vife| vife & 0x7F);
+        while let Some(vife) = iter.next() {
+            let u = vife & 0x08 != 0;
+            let f = vife & 0x04 != 0;
+            let b = vife & 0x01 != 0;
+            let modifier = match vife {
+                0x00..=0x0F | 0x15..=0x1C => VifModifier::Error(vife),
+                0x12 => VifModifier::AverageValue,
+                0x13 => VifModifier::InverseCompactProfile,
+                0x14 => VifModifier::RelativeDeviation,
+                0x1D => VifModifier::StandardConformDataContent,
+                0x1E => VifModifier::CompactProfileWithRegisterNumbers,
+                0x1F => VifModifier::CompactProfile,
+                0x20 => VifModifier::PerTime(Unit::Second),
+                0x21 => VifModifier::PerTime(Unit::Minute),
+                0x22 => VifModifier::PerTime(Unit::Hour),
+                0x23 => VifModifier::PerTime(Unit::Day),
+                0x24 => VifModifier::PerTime(Unit::Week),
+                0x25 => VifModifier::PerTime(Unit::Month),
+                0x26 => VifModifier::PerTime(Unit::Year),
+                0x27 => VifModifier::PerMeasurement,
+                0x28..=0x29 => VifModifier::IncrementPerInputPulse(vife & 0x01),
+                0x2A..=0x2B => VifModifier::IncrementPerOutputPulse(vife & 0x01),
+                0x2C => VifModifier::PerLitre,
+                0x2D => VifModifier::PerCubicMetre,
+                0x2E => VifModifier::PerKilogram,
+                0x2F => VifModifier::PerKelvin,
+                0x30 => VifModifier::PerKilowattHour,
+                0x31 => VifModifier::PerGigajoule,
+                0x32 => VifModifier::PerKilowatt,
+                0x33 => VifModifier::PerKelvinLitre,
+                0x34 => VifModifier::PerVolt,
+                0x35 => VifModifier::PerAmpere,
+                0x36 => VifModifier::MultipliedBySecond,
+                0x37 => VifModifier::MultipliedBySecondPerVolt,
+                0x38 => VifModifier::MultipliedBySecondPerAmpere,
+                0x39 => VifModifier::StartDateTime,
+                0x3A => VifModifier::Uncorrected,
+                0x3B => VifModifier::PositiveAccumulation,
+                0x3C => VifModifier::NegativeAccumulation,
+                0x3D => VifModifier::NonMetricUnits,
+                0x3E => VifModifier::ValueAtBaseConditions,
+                0x3F => VifModifier::ObisDeclaration,
+                0x40 | 0x48 => VifModifier::LimitValue { upper: u },
+                0x41 | 0x49 => VifModifier::LimitExceedCount { upper: u },
+                0x42 | 0x43 | 0x46 | 0x47 | 0x4A | 0x4B | 0x4E | 0x4F => VifModifier::LimitExceedDateTime {
+                    upper: u,
+                    last: f,
+                    end: b,
+                },
+                0x50..=0x5F => VifModifier::LimitExceedDuration {
+                    upper: u,
+                    last: f,
+                    unit: time_unit(vife),
+                },
+                0x60..=0x67 => VifModifier::Duration {
+                    last: f,
+                    unit: time_unit(vife),
+                },
+                0x6A | 0x6B | 0x6E | 0x6F => VifModifier::DateTime { last: f, end: b },
+                0x70..=0x77 => VifModifier::MultiplicativeCorrection((vife & 0x07) as i8 - 6),
+                0x78..=0x7B => VifModifier::AdditiveCorrection((vife & 0x03) as i8 - 3),
+                0x7C => match iter.next() {
+                    Some(extension) => VifModifier::Extension(extension),
+                    None => VifModifier::Reserved(vife),
+                },
+                0x7D => VifModifier::MultiplicativeCorrection(3),
+                0x7E => VifModifier::FutureValue,
+                0x7F => {
+                    modifiers.push(VifModifier::ManufacturerSpecific);
+                    break;
+                }
+                _ => VifModifier::Reserved(vife),
+            };
+            modifiers.push(modifier);
+        }
+        modifiers
+    }
+
+    /// Encode the modifiers as combinable VIFEs without the extension bits, i.e. the inverse of `decode`.
+    pub fn encode(modifiers: &[VifModifier]) -> Result<Vec<u8>, ()> {
+        let mut vife = Vec::new();
+        for &modifier in modifiers {
+            match modifier {
+                VifModifier::Extension(extension) => vife.extend_from_slice(&[0x7C, extension]),
+                _ => vife.push(
+                    (0x00..=0x7F)
+                        .filter(|&x| x != 0x7C)
+                        .find(|&x| VifModifier::decode(&[x]) == [modifier])
+                        .ok_or(())?,
+                ),
+            }
+        }
+        Ok(vife)
+    }
+
+    /// Get the record error, if the modifier is a known error code.
+    pub fn record_error(self) -> Option<RecordError> {
+        match self {
+            VifModifier::Error(code) => num_traits::FromPrimitive::from_u8(code),
+            _ => None,
+        }
+    }
+}
+
+/// Get the time unit of the two least significant bits of a duration VIF.
+fn time_unit(vif: u8) -> Unit {
+    match vif & 0x03 {
+        0 => Unit::Second,
+        1 => Unit::Minute,
+        2 => Unit::Hour,
+        _ => Unit::Day,
+    }
+}
+
+/// Get the time unit of the two least significant bits of a long duration VIF.
+fn long_time_unit(vif: u8) -> Unit {
+    match vif & 0x03 {
+        0 => Unit::Hour,
+        1 => Unit::Day,
+        2 => Unit::Month,
+        _ => Unit::Year,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn primary_vif() {
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::Energy, Unit::WattHour, 3)),
+            ValueInformation::from_primary(0x06)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::Volume, Unit::CubicMetre, -3)),
+            ValueInformation::from_primary(0x13)
+        );
+        // The extension bit is not part of the VIF
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::Volume, Unit::CubicMetre, -3)),
+            ValueInformation::from_primary(0x93)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::OperatingTime, Unit::Day, 0)),
+            ValueInformation::from_primary(0x27)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::VolumeFlow, Unit::CubicMetrePerHour, -3)),
+            ValueInformation::from_primary(0x3B)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::FlowTemperature, Unit::Celsius, -1)),
+            ValueInformation::from_primary(0x5A)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::TemperatureDifference, Unit::Kelvin, -2)),
+            ValueInformation::from_primary(0x61)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::DateTime, Unit::None, 0)),
+            ValueInformation::from_primary(0x6D)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::HeatCostAllocator, Unit::None, 0)),
+            ValueInformation::from_primary(0x6E)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::FabricationNumber, Unit::None, 0)),
+            ValueInformation::from_primary(0x78)
+        );
+    }
+
+    #[test]
+    pub fn first_extension_vif() {
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::AccessNumber, Unit::None, 0)),
+            ValueInformation::from_first_extension(0x08)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::ErrorFlags, Unit::None, 0)),
+            ValueInformation::from_first_extension(0x17)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::DigitalInput, Unit::None, 0)),
+            ValueInformation::from_first_extension(0x1B)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::Voltage, Unit::Volt, -3)),
+            ValueInformation::from_first_extension(0x46)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::Current, Unit::Ampere, -3)),
+            ValueInformation::from_first_extension(0xD9)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::BatteryOperatingTime, Unit::Month, 0)),
+            ValueInformation::from_first_extension(0x6E)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::RemainingBatteryLifetime, Unit::Day, 0)),
+            ValueInformation::from_first_extension(0x74)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::ResetCounter, Unit::None, 0)),
+            ValueInformation::from_first_extension(0x60)
+        );
+        assert_eq!(None, ValueInformation::from_first_extension(0x77));
+    }
+
+    #[test]
+    pub fn second_extension_vif() {
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::Energy, Unit::WattHour, 5)),
+            ValueInformation::from_second_extension(0x00)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::Energy, Unit::Joule, 9)),
+            ValueInformation::from_second_extension(0x09)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::ReactiveEnergy, Unit::VarHour, 3)),
+            ValueInformation::from_second_extension(0x02)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::Volume, Unit::CubicFoot, -1)),
+            ValueInformation::from_second_extension(0x21)
+        );
+        assert_eq!(
+            Some(ValueInformation::new(Quantity::RelativeHumidity, Unit::Percent, 0)),
+            ValueInformation::from_second_extension(0x1B)
+        );
+        assert_eq!(None, ValueInformation::from_second_extension(0x0A));
+    }
+
+    #[test]
+    pub fn combinable_vife() {
+        assert_eq!(
+            vec![
+                VifModifier::PerTime(Unit::Hour),
+                VifModifier::NegativeAccumulation,
+                VifModifier::FutureValue,
+            ],
+            VifModifier::decode(&[0xA2, 0xBC, 0x7E])
+        );
+        assert_eq!(
+            vec![
+                VifModifier::LimitExceedDateTime {
+                    upper: true,
+                    last: true,
+                    end: false,
+                },
+                VifModifier::LimitExceedDuration {
+                    upper: false,
+                    last: true,
+                    unit: Unit::Hour,
+                },
+                VifModifier::LimitExceedCount { upper: true },
+            ],
+            VifModifier::decode(&[0xCE, 0xD6, 0x49])
+        );
+        assert_eq!(
+            vec![
+                VifModifier::MultiplicativeCorrection(-3),
+                VifModifier::AdditiveCorrection(-1),
+                VifModifier::MultiplicativeCorrection(3),
+            ],
+            VifModifier::decode(&[0xF3, 0xFA, 0x7D])
+        );
+        assert_eq!(
+            vec![VifModifier::Extension(0x01), VifModifier::Reserved(0x10)],
+            VifModifier::decode(&[0xFC, 0x81, 0x10])
+        );
+        assert_eq!(
+            vec![
+                VifModifier::Duration {
+                    last: false,
+                    unit: Unit::Minute,
+                },
+                VifModifier::Duration {
+                    last: true,
+                    unit: Unit::Hour,
+                },
+                VifModifier::DateTime { last: false, end: true },
+                VifModifier::DateTime { last: true, end: false },
+                VifModifier::Reserved(0x68),
+                VifModifier::Reserved(0x6D),
+            ],
+            VifModifier::decode(&[0xE1, 0xE6, 0xEB, 0xEE, 0xE8, 0x6D])
+        );
+
+        // The VIFEs following a manufacturer specific VIFE are not decoded
+        assert_eq!(
+            vec![VifModifier::PerTime(Unit::Second), VifModifier::ManufacturerSpecific],
+            VifModifier::decode(&[0xA0, 0xFF, 0x20])
+        );
+    }
+
+    #[test]
+    pub fn encode_modifiers() {
+        let modifiers = [
+            VifModifier::PerTime(Unit::Hour),
+            VifModifier::Extension(0x01),
+            VifModifier::Duration {
+                last: true,
+                unit: Unit::Hour,
+            },
+            VifModifier::MultiplicativeCorrection(3),
+        ];
+        let vife = VifModifier::encode(&modifiers).unwrap();
+        assert_eq!(vec![0x22, 0x7C, 0x01, 0x66, 0x7D], vife);
+        assert_eq!(modifiers.to_vec(), VifModifier::decode(&vife));
+
+        assert!(VifModifier::encode(&[VifModifier::Reserved(0x7C)]).is_err());
+    }
+
+    #[test]
+    pub fn record_error() {
+        let modifiers = VifModifier::decode(&[0x18]);
+        assert_eq!(vec![VifModifier::Error(0x18)], modifiers);
+        assert_eq!(Some(RecordError::DataError), modifiers[0].record_error());
+        assert_eq!(None, VifModifier::Error(0x19).record_error());
+        assert_eq!(None, VifModifier::FutureValue.record_error());
+    }
+
+    #[test]
+    pub fn to_vif() {
+        assert_eq!(
+            Ok((0x13, None)),
+            ValueInformation::new(Quantity::Volume, Unit::CubicMetre, -3).to_vif()
+        );
+        assert_eq!(
+            Ok((0xFD, Some(0x17))),
+            ValueInformation::new(Quantity::ErrorFlags, Unit::None, 0).to_vif()
+        );
+        assert_eq!(
+            Ok((0xFB, Some(0x01))),
+            ValueInformation::new(Quantity::Energy, Unit::WattHour, 6).to_vif()
+        );
+        assert!(ValueInformation::new(Quantity::Volume, Unit::CubicMetre, 4).to_vif().is_err());
+        assert!(ValueInformation::new(Quantity::PlainText, Unit::None, 0).to_vif().is_err());
+    }
+
+    #[test]
+    pub fn reserved_vif() {
+        assert_eq!(None, ValueInformation::from_primary(0x6F));
+        assert_eq!(None, ValueInformation::from_primary(0xFB));
+        assert_eq!(None, ValueInformation::from_primary(0xFD));
+        assert_eq!(None, ValueInformation::from_primary(0xFF));
+    }
+}
@@ -1,25 +1,64 @@
-use core::convert::TryInto;
+use core::{cmp::min, convert::TryInto};
 
 use alloc::vec::Vec;
 use crc::{Crc, CRC_16_EN_13757};
 
-use crate::{bcd::BcdNumber, ffa::FrameFormatA, ffb::FrameFormatB, frameformat::FrameFormat, mbusaddress::MBusAddress};
+use crate::{authenticationlayer::{AuthenticationLayer, AuthenticationType}, bcd::BcdNumber, cifield::CiField, configurationfield::{ConfigurationFieldExtension, SecurityMode}, crypto::{CryptoBackend, DerivationConstant, Iv, BLOCK_SIZE}, datarecord::DataRecords, ffa::FrameFormatA, ffb::FrameFormatB, frameformat::FrameFormat, keystore::{KeyStore, UsedKeys}, mbusaddress::{FieldLayout, MBusAddress}, transportlayer::TransportLayer};
 
 const CRC: Crc<u16> = Crc::<u16>::new(&CRC_16_EN_13757);
 
+const CONTROL_FUNCTION_MASK: u8 = 0x4F;
+const SN_ENCRYPTION_SHIFT: u32 = 29;
+const SN_ENCRYPTION_MASK: u32 = 0x7 << SN_ENCRYPTION_SHIFT;
+
+#[derive(Clone)]
 pub struct WMBusPacket {
-    pub application_layer: ApplicationLayer,
+    pub application_layer: Option<ApplicationLayer>,
+    pub authentication_layer: Option<AuthenticationLayer>,
     pub ext_link_layer: Option<ExtendedLinkLayer>,
     pub link_layer: LinkLayer,
 }
 
+#[derive(Clone)]
 pub struct LinkLayer {
     pub length: Option<u8>,
     pub control: u8,
     pub address: MBusAddress,
 }
 
-#[derive(PartialEq)]
+/// The function of the control field, i.e. without the FCB/ACD and FCV/DFC bits.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive)]
+#[repr(u8)]
+pub enum FunctionCode {
+    Ack = 0x00,
+    Nack = 0x01,
+    CnfIr = 0x06,
+    RspUd = 0x08,
+    SndNke = 0x40,
+    SndUd = 0x43,
+    SndNr = 0x44,
+    SndIr = 0x46,
+    AccNr = 0x47,
+    AccDmd = 0x48,
+    ReqUd1 = 0x4A,
+    ReqUd2 = 0x4B,
+}
+
+impl LinkLayer {
+    pub const FCB: u8 = 0x20;
+    pub const FCV: u8 = 0x10;
+
+    pub fn function_code(&self) -> Option<FunctionCode> {
+        num_traits::FromPrimitive::from_u8(self.control & CONTROL_FUNCTION_MASK)
+    }
+
+    /// Get the frame count bit, only meaningful for SND_UD and REQ_UD frames.
+    pub fn fcb(&self) -> bool {
+        self.control & Self::FCB != 0
+    }
+}
+
+#[derive(Clone, PartialEq)]
 pub enum ExtendedLinkLayer {
     Short {
         cc: u8,
@@ -45,8 +84,76 @@ pub enum ExtendedLinkLayer {
     },
 }
 
+/// The communication control field of the extended link layer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CommunicationControl(pub u8);
+
+impl CommunicationControl {
+    pub const BIDIRECTIONAL: u8 = 0x80;
+    pub const RESPONSE_DELAY: u8 = 0x40;
+    pub const SYNCHRONIZED: u8 = 0x20;
+    pub const HOP_COUNT: u8 = 0x10;
+    pub const PRIORITY: u8 = 0x08;
+    pub const ACCESSIBILITY: u8 = 0x04;
+    pub const REPEATED_ACCESS: u8 = 0x02;
+
+    pub fn bidirectional(self) -> bool {
+        self.0 & Self::BIDIRECTIONAL != 0
+    }
+
+    /// Get whether the fast response delay is used, otherwise the slow.
+    pub fn fast_response_delay(self) -> bool {
+        self.0 & Self::RESPONSE_DELAY != 0
+    }
+
+    pub fn synchronized(self) -> bool {
+        self.0 & Self::SYNCHRONIZED != 0
+    }
+
+    pub fn hop_count(self) -> u8 {
+        (self.0 & Self::HOP_COUNT != 0) as u8
+    }
+
+    /// Get the communication control for the frame when transmitted by a repeater.
+    pub fn repeated(self) -> Self {
+        Self(self.0 | Self::HOP_COUNT)
+    }
+
+    pub fn priority(self) -> bool {
+        self.0 & Self::PRIORITY != 0
+    }
+
+    pub fn accessibility(self) -> bool {
+        self.0 & Self::ACCESSIBILITY != 0
+    }
+
+    pub fn repeated_access(self) -> bool {
+        self.0 & Self::REPEATED_ACCESS != 0
+    }
+}
+
+/// The encryption field of the session number in the long extended link layer.
+#[derive(Clone, Copy, Debug, PartialEq, FromPrimitive)]
+#[repr(u8)]
+pub enum EllEncryption {
+    None = 0,
+    AesCtr = 1,
+}
+
 impl ExtendedLinkLayer {
+    /// Parse the layer from the data following the link layer, or get None if there is no extended link layer.
     pub fn parse(rest: &[u8]) -> Result<Option<ExtendedLinkLayer>, ()> {
+        let size = match rest.first() {
+            Some(0x8C) => 1 + 2,
+            Some(0x8D) => 1 + 8,
+            Some(0x8E) => 1 + 10,
+            Some(0x8F) => 1 + 16,
+            _ => return Ok(None),
+        };
+        if rest.len() < size {
+            return Err(());
+        }
+
         let ell = match rest[0] {
             0x8C => Some(ExtendedLinkLayer::Short {
                 cc: rest[1],
@@ -74,6 +181,7 @@ impl ExtendedLinkLayer {
         };
         Ok(ell)
     }
+
     pub fn size(&self) -> usize {
         match *self {
             ExtendedLinkLayer::Short { .. } => 1 + 2,
@@ -82,20 +190,186 @@ impl ExtendedLinkLayer {
             ExtendedLinkLayer::LongDest { .. } => 1 + 16,
         }
     }
+
+    /// Write the extended link layer where a missing payload crc is computed from the following payload.
+    pub fn write(&self, buf: &mut Vec<u8>, rest: &[u8]) {
+        match self {
+            ExtendedLinkLayer::Short { cc, acc } => {
+                buf.extend_from_slice(&[0x8C, *cc, *acc]);
+            }
+            ExtendedLinkLayer::Long { cc, acc, sn, payload_crc } => {
+                buf.extend_from_slice(&[0x8D, *cc, *acc]);
+                buf.extend_from_slice(&sn.to_le_bytes());
+                buf.extend_from_slice(&payload_crc.unwrap_or_else(|| CRC.checksum(rest)).to_le_bytes());
+            }
+            ExtendedLinkLayer::ShortDest { cc, acc, dest } => {
+                buf.extend_from_slice(&[0x8E, *cc, *acc]);
+                buf.extend_from_slice(&dest.to_bytes());
+            }
+            ExtendedLinkLayer::LongDest { cc, acc, dest, sn, payload_crc } => {
+                buf.extend_from_slice(&[0x8F, *cc, *acc]);
+                buf.extend_from_slice(&dest.to_bytes());
+                buf.extend_from_slice(&sn.to_le_bytes());
+                buf.extend_from_slice(&payload_crc.unwrap_or_else(|| CRC.checksum(rest)).to_le_bytes());
+            }
+        }
+    }
+
+    pub fn cc(&self) -> u8 {
+        match *self {
+            ExtendedLinkLayer::Short { cc, .. } => cc,
+            ExtendedLinkLayer::Long { cc, .. } => cc,
+            ExtendedLinkLayer::ShortDest { cc, .. } => cc,
+            ExtendedLinkLayer::LongDest { cc, .. } => cc,
+        }
+    }
+
+    pub fn communication_control(&self) -> CommunicationControl {
+        CommunicationControl(self.cc())
+    }
+
+    pub fn acc(&self) -> u8 {
+        match *self {
+            ExtendedLinkLayer::Short { acc, .. } => acc,
+            ExtendedLinkLayer::Long { acc, .. } => acc,
+            ExtendedLinkLayer::ShortDest { acc, .. } => acc,
+            ExtendedLinkLayer::LongDest { acc, .. } => acc,
+        }
+    }
+
+    pub fn sn(&self) -> Option<u32> {
+        match *self {
+            ExtendedLinkLayer::Long { sn, .. } => Some(sn),
+            ExtendedLinkLayer::LongDest { sn, .. } => Some(sn),
+            _ => None,
+        }
+    }
+
+    /// Get the session number without the encryption bits, which increases for each transmission.
+    pub fn session_number(&self) -> Option<u32> {
+        self.sn().map(|sn| sn & !SN_ENCRYPTION_MASK)
+    }
+
+    /// Get the encryption used for the payload, or None if there is no session number.
+    pub fn encryption(&self) -> Option<EllEncryption> {
+        self.sn()
+            .and_then(|sn| num_traits::FromPrimitive::from_u32(sn >> SN_ENCRYPTION_SHIFT))
+    }
+
+    /// Get the AES-128-CTR initialization vector, i.e. M, A, CC, SN, FN and BC.
+    /// The hop count is excluded from the CC field as it is changed by repeaters.
+    fn iv(&self, address: &MBusAddress) -> Option<Iv> {
+        self.sn().map(|sn| {
+            let mut iv = [0; 16];
+            iv[0..8].copy_from_slice(&address.to_bytes());
+            iv[8] = self.cc() & !CommunicationControl::HOP_COUNT;
+            iv[9..13].copy_from_slice(&sn.to_le_bytes());
+            // The frame number and the block counter are both zero
+            iv
+        })
+    }
 }
 
+#[derive(Clone)]
 pub struct ApplicationLayer {
     pub ci: u8,
     pub data: Vec<u8>,
 }
 
+impl ApplicationLayer {
+    pub fn ci_field(&self) -> CiField {
+        CiField::from(self.ci)
+    }
+
+    pub fn transport_layer(&self) -> Result<Option<TransportLayer>, ()> {
+        TransportLayer::parse(self.ci_field(), &self.data)
+    }
+
+    /// Get the data following the transport layer header.
+    pub fn application_data(&self) -> Result<&[u8], ()> {
+        let tpl_size = match self.transport_layer()? {
+            Some(tpl) => tpl.size(),
+            None => 0,
+        };
+        Ok(&self.data[tpl_size..])
+    }
+
+    /// Parse the data records of the EN13757-3 variable data structure following the transport layer header.
+    /// The data must not be encrypted.
+    pub fn data_records(&self) -> Result<DataRecords, ()> {
+        let start = self.data_records_start()?;
+        DataRecords::parse(&self.data[start..])
+    }
+
+    /// Replace the data following the transport layer header with the data records.
+    pub fn set_data_records(&mut self, records: &DataRecords) -> Result<(), ()> {
+        let start = self.data_records_start()?;
+        self.data.truncate(start);
+        records.write(&mut self.data);
+        Ok(())
+    }
+
+    /// Get the start of the unencrypted data records following the transport layer header.
+    fn data_records_start(&self) -> Result<usize, ()> {
+        match self.ci_field() {
+            CiField::ApplicationLayer(_) | CiField::Command(_) => {}
+            _ => return Err(()),
+        }
+        match self.transport_layer()? {
+            Some(tpl) if tpl.configuration().security_mode() != Some(SecurityMode::Mode0) => Err(()),
+            Some(tpl) => Ok(tpl.size()),
+            None => Ok(0),
+        }
+    }
+
+    /// Encrypt the data following the transport layer header and mark it with the security mode in the configuration field.
+    /// The data is prefixed with the 0x2F2F check and padded with 0x2F filler to a whole number of blocks.
+    fn encrypt_tpl<F: FnOnce(&mut [u8]) -> Result<(), ()>>(
+        &mut self,
+        mode: SecurityMode,
+        extension: Option<ConfigurationFieldExtension>,
+        encrypt: F,
+    ) -> Result<(), ()> {
+        let tpl = self.transport_layer()?.ok_or(())?;
+        let mut configuration = tpl.configuration();
+        if configuration.security_mode() != Some(SecurityMode::Mode0) {
+            return Err(());
+        }
+
+        let start = tpl.size();
+        let mut encrypted = vec![0x2F, 0x2F];
+        encrypted.extend_from_slice(&self.data[start..]);
+        while encrypted.len() % BLOCK_SIZE != 0 {
+            encrypted.push(0x2F);
+        }
+        let blocks = encrypted.len() / BLOCK_SIZE;
+        if blocks > 0x0F {
+            return Err(());
+        }
+        encrypt(&mut encrypted)?;
+
+        let configuration_start = start - configuration.size();
+        configuration.mode = mode as u8;
+        configuration.encrypted_blocks = blocks as u8;
+        configuration.extension = extension;
+        let mut configuration_bytes = Vec::with_capacity(3);
+        configuration.write(&mut configuration_bytes);
+
+        self.data.truncate(start);
+        self.data.extend_from_slice(&encrypted);
+        self.data.splice(configuration_start..start, configuration_bytes);
+        Ok(())
+    }
+}
+
 impl WMBusPacket {
     pub fn request() -> Self {
         Self {
-            application_layer: ApplicationLayer {
+            application_layer: Some(ApplicationLayer {
                 ci: 0x00,
                 data: vec![],
-            },
+            }),
+            authentication_layer: None,
             ext_link_layer: None,
             link_layer: LinkLayer {
                 length: None,
@@ -105,11 +379,37 @@ impl WMBusPacket {
                     serial_number: BcdNumber::new_u32(0).unwrap(),
                     version: 0,
                     device_type: 0,
+                    layout: FieldLayout::Default,
                 }
             }
         }
     }
 
+    pub fn transport_layer(&self) -> Result<Option<TransportLayer>, ()> {
+        match &self.application_layer {
+            Some(apl) => apl.transport_layer(),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the address of the meter.
+    /// The meter address is in the long transport layer header if present, otherwise in the link layer.
+    pub fn meter_address(&self) -> MBusAddress {
+        match self.transport_layer() {
+            Ok(Some(TransportLayer::Long { address, .. })) => address,
+            _ => self.link_layer.address,
+        }
+    }
+
+    /// Get the address of the radio adapter transmitting on behalf of the meter.
+    /// The radio adapter address is in the link layer if the meter address is in the long transport layer header.
+    pub fn radio_adapter_address(&self) -> Option<MBusAddress> {
+        match self.transport_layer() {
+            Ok(Some(TransportLayer::Long { .. })) => Some(self.link_layer.address),
+            _ => None,
+        }
+    }
+
     pub fn parse_ffa(frame_bytes: &[u8]) -> Result<Self, ()> {
         Self::parse(FrameFormatA, frame_bytes)
     }
@@ -118,31 +418,276 @@ impl WMBusPacket {
         Self::parse(FrameFormatB, frame_bytes)
     }
 
-    fn parse<FF: FrameFormat>(_frame_format: FF, frame_bytes: &[u8]) -> Result<Self, ()> {
-        // Verify CRC
-        let mut payload = Vec::with_capacity(frame_bytes.len());
-        let mut digest = CRC.digest();
-        for (index, block) in FF::frame_block_iter(frame_bytes)?.enumerate() {
-            if FF::block_has_crc(index) {
-                let block_payload = &block[..block.len() - 2];
-                payload.extend_from_slice(block_payload);
-                digest.update(block_payload);
-                let actual_checksum = digest.finalize();
-
-                // Verify checksum
-                let expected = u16::from_be_bytes(block[block.len() - 2..].try_into().unwrap());
-                if actual_checksum != expected {
+    /// Decrypt the payload following the long extended link layer using AES-128-CTR.
+    /// The decrypted payload crc is verified, and the packet is only updated if it matches.
+    pub fn decrypt_ell<B: CryptoBackend>(&mut self, backend: &B, key: &B::KeyHandle) -> Result<(), ()> {
+        let ell = self.ext_link_layer.as_mut().ok_or(())?;
+        if ell.encryption() != Some(EllEncryption::AesCtr) {
+            return Err(());
+        }
+        let iv = ell.iv(&self.link_layer.address).ok_or(())?;
+        let (sn, payload_crc) = match ell {
+            ExtendedLinkLayer::Long { sn, payload_crc, .. } => (sn, payload_crc),
+            ExtendedLinkLayer::LongDest { sn, payload_crc, .. } => (sn, payload_crc),
+            _ => return Err(()),
+        };
+
+        // The encryption starts at the payload crc
+        let apl = self.application_layer.as_ref().ok_or(())?;
+        let mut payload = Vec::with_capacity(2 + 1 + apl.data.len());
+        payload.extend_from_slice(&payload_crc.ok_or(())?.to_le_bytes());
+        payload.push(apl.ci);
+        payload.extend_from_slice(&apl.data);
+        backend.aes_ctr(key, &iv, &mut payload)?;
+
+        // Verify payload checksum
+        let expected = u16::from_le_bytes(payload[0..2].try_into().unwrap());
+        if CRC.checksum(&payload[2..]) != expected {
+            return Err(());
+        }
+
+        let (afl, apl) = split_application_layer(&payload[2..], true)?;
+        *sn &= !SN_ENCRYPTION_MASK;
+        *payload_crc = Some(expected);
+        self.authentication_layer = afl;
+        self.application_layer = apl;
+
+        Ok(())
+    }
+
+    /// Verify the AES-128-CMAC in the authentication and fragmentation layer,
+    /// where the MAC key is derived from the master key, the message counter and the meter id.
+    /// The AES-128-GMAC of security mode 10 is verified by decrypting a copy of the packet.
+    pub fn verify_mac<B: CryptoBackend>(&self, backend: &B, key: &B::KeyHandle) -> Result<(), ()> {
+        let afl = self.authentication_layer.as_ref().ok_or(())?;
+        if afl.authentication_type() == Some(AuthenticationType::AesGmac96) {
+            let tpl = self.transport_layer()?.ok_or(())?;
+            if tpl.configuration().security_mode() != Some(SecurityMode::Mode10) {
+                return Err(());
+            }
+            return self.clone().decrypt_tpl(backend, key);
+        }
+
+        let apl = self.application_layer.as_ref().ok_or(())?;
+        let counter = afl.mcr.ok_or(())?;
+        let id = meter_id(&self.meter_address());
+        let mac_key = backend.derive_key(key, DerivationConstant::Kmac, counter, id)?;
+
+        let mut message = Vec::with_capacity(1 + apl.data.len());
+        message.push(apl.ci);
+        message.extend_from_slice(&apl.data);
+        afl.verify_mac(backend, &mac_key, &message)
+    }
+
+    /// Decrypt the application data according to the security mode in the transport layer configuration field.
+    /// For security mode 7 the key is the master key from which the ephemeral keys are derived,
+    /// and the MAC in the authentication and fragmentation layer is verified before decryption.
+    /// For security mode 10 all data following the transport layer header is decrypted and authenticated
+    /// by the tag in the authentication and fragmentation layer.
+    /// For the other modes the decrypted data must start with the 0x2F2F check, and the packet is only updated if it does.
+    /// Any unencrypted data following the encrypted blocks is left as is.
+    pub fn decrypt_tpl<B: CryptoBackend>(&mut self, backend: &B, key: &B::KeyHandle) -> Result<(), ()> {
+        let address = self.meter_address();
+        let tpl = self.transport_layer()?.ok_or(())?;
+        let mut configuration = tpl.configuration();
+        if configuration.security_mode() == Some(SecurityMode::Mode7) {
+            self.verify_mac(backend, key)?;
+        }
+
+        let apl = self.application_layer.as_mut().ok_or(())?;
+        let start = tpl.size();
+        let end = match configuration.security_mode() {
+            Some(SecurityMode::Mode10) => apl.data.len(),
+            _ => start + BLOCK_SIZE * configuration.encrypted_blocks as usize,
+        };
+        let mut decrypted = apl.data.get(start..end).ok_or(())?.to_vec();
+        match configuration.security_mode() {
+            Some(SecurityMode::Mode5) => {
+                backend.aes_cbc_decrypt(key, &mode5_iv(&address, tpl.acc()), &mut decrypted)?;
+            }
+            Some(SecurityMode::Mode7) => {
+                // Only the KDF-A key derivation is defined
+                if configuration.extension.map(|x| x.kdf()) != Some(1) {
+                    return Err(());
+                }
+                let counter = self.authentication_layer.as_ref().and_then(|afl| afl.mcr).ok_or(())?;
+                let id = meter_id(&address);
+                let enc_key = backend.derive_key(key, DerivationConstant::Kenc, counter, id)?;
+                backend.aes_cbc_decrypt(&enc_key, &[0; 16], &mut decrypted)?;
+            }
+            Some(SecurityMode::Mode10) => {
+                let afl = self.authentication_layer.as_ref().ok_or(())?;
+                if afl.authentication_type() != Some(AuthenticationType::AesGmac96) {
                     return Err(());
                 }
+                let counter = afl.mcr.ok_or(())?;
+                let mut iv = [0; 12];
+                iv[0..8].copy_from_slice(&address.to_bytes());
+                iv[8..12].copy_from_slice(&counter.to_le_bytes());
 
-                digest = CRC.digest();
-            } else {
-                // Append the entire block to the digest
-                payload.extend_from_slice(block);
-                digest.update(block);
+                // The message control, the message counter and the transport layer header are authenticated
+                let mut aad = vec![afl.mcl.ok_or(())?];
+                aad.extend_from_slice(&counter.to_le_bytes());
+                aad.push(apl.ci);
+                aad.extend_from_slice(&apl.data[..start]);
+
+                backend.aes_gcm_decrypt(key, &iv, &aad, &mut decrypted, afl.mac.as_ref().ok_or(())?)?;
             }
+            _ => return Err(()),
+        }
+
+        if configuration.security_mode() != Some(SecurityMode::Mode10) && !decrypted.starts_with(&[0x2F, 0x2F]) {
+            return Err(());
+        }
+        apl.data[start..end].copy_from_slice(&decrypted);
+
+        // Mark the application data as unencrypted
+        let configuration_start = start - configuration.size();
+        configuration.mode = SecurityMode::Mode0 as u8;
+        configuration.encrypted_blocks = 0;
+        configuration.extension = None;
+        let mut configuration_bytes = Vec::with_capacity(2);
+        configuration.write(&mut configuration_bytes);
+        apl.data.splice(configuration_start..start, configuration_bytes);
+
+        Ok(())
+    }
+
+    /// Decrypt the extended link layer and the transport layer with the candidate keys in the key store.
+    /// The extended link layer keys are looked up by the link layer address,
+    /// and the transport layer keys by the meter address.
+    pub fn decrypt<B: CryptoBackend, K: KeyStore<B::KeyHandle>>(
+        &mut self,
+        backend: &B,
+        key_store: &K,
+    ) -> Result<UsedKeys, ()> {
+        let mut used = UsedKeys::default();
+
+        // Decrypt a copy so that the packet is left untouched if any layer fails
+        let mut packet = self.clone();
+        let ell_encryption = packet.ext_link_layer.as_ref().and_then(|ell| ell.encryption());
+        if matches!(ell_encryption, Some(encryption) if encryption != EllEncryption::None) {
+            let keys = key_store.keys(&packet.link_layer.address);
+            used.ell = Some(keys.iter().position(|key| packet.decrypt_ell(backend, key).is_ok()).ok_or(())?);
+        }
+
+        if matches!(packet.transport_layer()?, Some(tpl) if tpl.configuration().mode != SecurityMode::Mode0 as u8) {
+            let keys = key_store.keys(&packet.meter_address());
+            used.tpl = Some(keys.iter().position(|key| packet.decrypt_tpl(backend, key).is_ok()).ok_or(())?);
+        }
+
+        *self = packet;
+        Ok(used)
+    }
+
+    /// Encrypt the payload following the long extended link layer using AES-128-CTR.
+    pub fn encrypt_ell<B: CryptoBackend>(&mut self, backend: &B, key: &B::KeyHandle) -> Result<(), ()> {
+        if self.application_layer.is_none() {
+            return Err(());
+        }
+        let ell = self.ext_link_layer.as_mut().ok_or(())?;
+        if ell.encryption() != Some(EllEncryption::None) {
+            return Err(());
+        }
+        if let ExtendedLinkLayer::Long { sn, .. } | ExtendedLinkLayer::LongDest { sn, .. } = ell {
+            *sn = *sn & !SN_ENCRYPTION_MASK | (EllEncryption::AesCtr as u32) << SN_ENCRYPTION_SHIFT;
+        }
+        let iv = ell.iv(&self.link_layer.address).ok_or(())?;
+
+        let mut payload = application_bytes(&self.authentication_layer, &self.application_layer);
+        let checksum = CRC.checksum(&payload);
+        payload.splice(0..0, checksum.to_le_bytes());
+        backend.aes_ctr(key, &iv, &mut payload)?;
+
+        if let ExtendedLinkLayer::Long { payload_crc, .. } | ExtendedLinkLayer::LongDest { payload_crc, .. } = ell {
+            *payload_crc = Some(u16::from_le_bytes(payload[0..2].try_into().unwrap()));
+        }
+        self.authentication_layer = None;
+        self.application_layer = Some(ApplicationLayer {
+            ci: payload[2],
+            data: payload[3..].to_vec(),
+        });
+
+        Ok(())
+    }
+
+    /// Encrypt the application data following the transport layer header using security mode 5.
+    pub fn encrypt_mode5<B: CryptoBackend>(&mut self, backend: &B, key: &B::KeyHandle) -> Result<(), ()> {
+        let tpl = self.transport_layer()?.ok_or(())?;
+        let iv = mode5_iv(&self.meter_address(), tpl.acc());
+        let apl = self.application_layer.as_mut().ok_or(())?;
+        apl.encrypt_tpl(SecurityMode::Mode5, None, |data| backend.aes_cbc_encrypt(key, &iv, data))
+    }
+
+    /// Encrypt the application data following the transport layer header using security mode 7,
+    /// where the ephemeral keys are derived from the master key and the message counter.
+    /// The authentication and fragmentation layer with the message counter and the MAC is prepended.
+    pub fn encrypt_mode7<B: CryptoBackend>(
+        &mut self,
+        backend: &B,
+        key: &B::KeyHandle,
+        counter: u32,
+    ) -> Result<(), ()> {
+        let id = meter_id(&self.meter_address());
+        if self.authentication_layer.is_some() {
+            return Err(());
+        }
+        let apl = self.application_layer.as_mut().ok_or(())?;
+
+        let enc_key = backend.derive_key(key, DerivationConstant::Kenc, counter, id)?;
+        apl.encrypt_tpl(SecurityMode::Mode7, Some(ConfigurationFieldExtension::new(1, 0)), |data| {
+            backend.aes_cbc_encrypt(&enc_key, &[0; 16], data)
+        })?;
+
+        let mut afl = AuthenticationLayer {
+            fcl: 0,
+            mcl: Some(AuthenticationType::AesCmac64 as u8 | AuthenticationLayer::MCR_IN_MAC),
+            ki: None,
+            mcr: Some(counter),
+            mac: None,
+            ml: None,
+        };
+        let mut message = Vec::with_capacity(1 + apl.data.len());
+        message.push(apl.ci);
+        message.extend_from_slice(&apl.data);
+        let mac_key = backend.derive_key(key, DerivationConstant::Kmac, counter, id)?;
+        afl.mac = Some(afl.compute_mac(backend, &mac_key, &message)?);
+
+        self.authentication_layer = Some(afl);
+
+        Ok(())
+    }
+
+    pub fn to_ffa(&self) -> Result<Vec<u8>, ()> {
+        frame(FrameFormatA, self.to_payload())
+    }
+
+    pub fn to_ffb(&self) -> Result<Vec<u8>, ()> {
+        frame(FrameFormatB, self.to_payload())
+    }
+
+    fn parse<FF: FrameFormat>(frame_format: FF, frame_bytes: &[u8]) -> Result<Self, ()> {
+        Self::parse_payload(&payload(frame_format, frame_bytes)?)
+    }
+
+    /// Get the payload without the L-field value, which is set when the frame is created.
+    /// The address is written in the field layout it was parsed with.
+    fn to_payload(&self) -> Vec<u8> {
+        let apl_bytes = application_bytes(&self.authentication_layer, &self.application_layer);
+
+        let mut payload = vec![0, self.link_layer.control];
+        payload.extend_from_slice(&self.link_layer.address.to_bytes());
+        if let Some(ell) = &self.ext_link_layer {
+            ell.write(&mut payload, &apl_bytes);
         }
+        payload.extend_from_slice(&apl_bytes);
+        payload
+    }
 
+    pub(crate) fn parse_payload(payload: &[u8]) -> Result<Self, ()> {
+        if payload.len() < 10 {
+            return Err(());
+        }
         let ll = LinkLayer {
             length: Some(payload[0]),
             control: payload[1],
@@ -158,23 +703,139 @@ impl WMBusPacket {
             0
         };
 
-        let rest = &rest[ell_size..];
-        let apl = ApplicationLayer {
-            ci: rest[0],
-            data: rest[1..].to_vec(),
-        };
+        // The authentication and fragmentation layer is encrypted together with the application layer
+        let ell_encryption = ell.as_ref().and_then(|ell| ell.encryption());
+        let ell_encrypted = matches!(ell_encryption, Some(encryption) if encryption != EllEncryption::None);
+        let (afl, apl) = split_application_layer(&rest[ell_size..], !ell_encrypted)?;
 
         Ok(Self {
             application_layer: apl,
+            authentication_layer: afl,
             ext_link_layer: ell,
             link_layer: ll,
         })
     }
 }
 
+/// Get the security mode 5 initialization vector, i.e. M and A as transmitted and eight times the access number.
+fn mode5_iv(address: &MBusAddress, acc: u8) -> Iv {
+    let mut iv = [acc; 16];
+    iv[0..8].copy_from_slice(&address.to_bytes());
+    iv
+}
+
+/// Get the meter id of the key derivation, i.e. the identification number field of the address as transmitted.
+fn meter_id(address: &MBusAddress) -> [u8; 4] {
+    address.to_bytes()[2..6].try_into().unwrap()
+}
+
+/// Split the bytes following the extended link layer into the authentication and fragmentation layer if present,
+/// and the application layer if any bytes remain, e.g. frames such as ACK may end after the extended link layer.
+fn split_application_layer(
+    rest: &[u8],
+    with_afl: bool,
+) -> Result<(Option<AuthenticationLayer>, Option<ApplicationLayer>), ()> {
+    let (afl, rest) = match rest.first() {
+        Some(&ci) if with_afl && CiField::from(ci) == CiField::AuthenticationFragmentationLayer => {
+            let afl = AuthenticationLayer::parse(&rest[1..])?;
+            let size = afl.size();
+            (Some(afl), &rest[1 + size..])
+        }
+        _ => (None, rest),
+    };
+
+    let apl = rest.first().map(|&ci| ApplicationLayer {
+        ci,
+        data: rest[1..].to_vec(),
+    });
+    Ok((afl, apl))
+}
+
+/// Get the bytes of the authentication and fragmentation layer and the application layer, starting with the CI field.
+fn application_bytes(afl: &Option<AuthenticationLayer>, apl: &Option<ApplicationLayer>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    if let Some(afl) = afl {
+        bytes.push(CiField::AuthenticationFragmentationLayer.into());
+        afl.write(&mut bytes);
+    }
+    if let Some(apl) = apl {
+        bytes.push(apl.ci);
+        bytes.extend_from_slice(&apl.data);
+    }
+    bytes
+}
+
+/// Get the payload of a frame with verified and removed CRC fields.
+pub(crate) fn payload<FF: FrameFormat>(_frame_format: FF, frame_bytes: &[u8]) -> Result<Vec<u8>, ()> {
+    // Verify CRC
+    let mut payload = Vec::with_capacity(frame_bytes.len());
+    let mut digest = CRC.digest();
+    for (index, block) in FF::frame_block_iter(frame_bytes)?.enumerate() {
+        if FF::block_has_crc(index) {
+            let block_payload = &block[..block.len() - 2];
+            payload.extend_from_slice(block_payload);
+            digest.update(block_payload);
+            let actual_checksum = digest.finalize();
+
+            // Verify checksum
+            let expected = u16::from_be_bytes(block[block.len() - 2..].try_into().unwrap());
+            if actual_checksum != expected {
+                return Err(());
+            }
+
+            digest = CRC.digest();
+        } else {
+            // Append the entire block to the digest
+            payload.extend_from_slice(block);
+            digest.update(block);
+        }
+    }
+
+    Ok(payload)
+}
+
+/// Create a frame from a payload by setting the L-field and adding the CRC fields.
+pub(crate) fn frame<FF: FrameFormat>(_frame_format: FF, mut payload: Vec<u8>) -> Result<Vec<u8>, ()> {
+    let block_count = FF::block_count_from_payload_size(payload.len())?;
+    let crc_count = (0..block_count).filter(|index| FF::block_has_crc(*index)).count();
+    let length = if FF::length_includes_crc() {
+        payload.len() - 1 + 2 * crc_count
+    } else {
+        payload.len() - 1
+    };
+    payload[0] = length.try_into().map_err(|_| ())?;
+
+    let mut frame_bytes = Vec::with_capacity(payload.len() + 2 * crc_count);
+    let mut digest = CRC.digest();
+    let mut offset = 0;
+    for index in 0..block_count {
+        let block_size = min(payload.len() - offset, FF::block_max_payload_size(index));
+        let block = &payload[offset..offset + block_size];
+        frame_bytes.extend_from_slice(block);
+        digest.update(block);
+        offset += block_size;
+
+        if FF::block_has_crc(index) {
+            frame_bytes.extend_from_slice(&digest.finalize().to_be_bytes());
+            digest = CRC.digest();
+        }
+    }
+
+    Ok(frame_bytes)
+}
+
 #[cfg(test)]
 pub mod tests {
-    use crate::mbusaddress::{DeviceType, ManufacturerCode};
+    use crate::{
+        cifield::TransportHeader,
+        configurationfield::{ConfigurationFieldExtension, SecurityMode},
+        crypto::{tests::MockCrypto, Key, SoftwareCrypto},
+        datarecord::{Function, TypedRecord},
+        keystore::MemoryKeyStore,
+        mbusaddress::{DeviceType, ManufacturerCode},
+        value::Value,
+        vif::{Quantity, Unit, ValueInformation},
+    };
 
     use super::*;
 
@@ -205,9 +866,15 @@ pub mod tests {
             packet.link_layer.address.device_type().unwrap()
         );
         assert!(packet.ext_link_layer.is_none());
-        assert_eq!(0x7A, packet.application_layer.ci);
-        assert_eq!(0xA6, packet.application_layer.data[0]);
-        assert_eq!(0xAD, *packet.application_layer.data.last().unwrap());
+        let apl = packet.application_layer.unwrap();
+        assert_eq!(0x7A, apl.ci);
+        assert_eq!(CiField::ApplicationLayer(TransportHeader::Short), apl.ci_field());
+        assert_eq!(0xA6, apl.data[0]);
+        assert_eq!(0xAD, *apl.data.last().unwrap());
+        let tpl = apl.transport_layer().unwrap().unwrap();
+        assert_eq!(0xA6, tpl.acc());
+        assert_eq!(Some(SecurityMode::Mode5), tpl.configuration().security_mode());
+        assert_eq!(0x6D, apl.application_data().unwrap()[0]);
     }
 
     #[test]
@@ -231,9 +898,638 @@ pub mod tests {
             DeviceType::Repeater,
             packet.link_layer.address.device_type().unwrap()
         );
+        assert_eq!(Some(FunctionCode::SndNr), packet.link_layer.function_code());
         assert!(packet.ext_link_layer.is_none());
-        assert_eq!(0xA0, packet.application_layer.ci);
-        assert_eq!(0x00, packet.application_layer.data[0]);
-        assert_eq!(0x06, *packet.application_layer.data.last().unwrap());
+        let apl = packet.application_layer.unwrap();
+        assert_eq!(0xA0, apl.ci);
+        assert_eq!(CiField::ManufacturerSpecific(0xA0), apl.ci_field());
+        assert_eq!(0x00, apl.data[0]);
+        assert_eq!(0x06, *apl.data.last().unwrap());
+    }
+
+    #[test]
+    pub fn can_write_ffa() {
+        let frame_bytes = [
+            0x4E, 0x44, 0x2D, 0x2C, 0x98, 0x27, 0x04, 0x67, 0x30, 0x04, 0x91, 0x53, 0x7A, 0xA6,
+            0x10, 0x40, 0x25, 0x6D, 0x3C, 0xA0, 0xF7, 0x2F, 0xF1, 0xEF, 0x06, 0x80, 0x6C, 0x50,
+            0xA1, 0x04, 0x21, 0xCB, 0xD1, 0x32, 0xE3, 0xB1, 0xD0, 0x11, 0x6A, 0x05, 0x57, 0x69,
+            0x6E, 0x0E, 0x37, 0xC2, 0xE9, 0xF0, 0x86, 0x36, 0xFE, 0x31, 0xF6, 0x8E, 0x6B, 0x4D,
+            0xEE, 0x5E, 0x38, 0x53, 0x16, 0xC2, 0x16, 0xA9, 0x6E, 0x27, 0x7D, 0x48, 0xB1, 0x45,
+            0x92, 0x72, 0x38, 0x61, 0x46, 0xF7, 0x8C, 0x77, 0x66, 0xD5, 0x19, 0xFC, 0x44, 0x49,
+            0x99, 0x3A, 0xDA, 0x5A, 0xAD, 0x95, 0xA5,
+        ];
+        let packet = WMBusPacket::parse_ffa(&frame_bytes).unwrap();
+        assert_eq!(frame_bytes.to_vec(), packet.to_ffa().unwrap());
+    }
+
+    #[test]
+    pub fn can_write_ffb() {
+        let frame_bytes = [
+            0x13, 0x44, 0x2D, 0x2C, 0x78, 0x56, 0x34, 0x12, 0x01, 0x32, 0xA0, 0x00, 0x01, 0x02,
+            0x03, 0x04, 0x05, 0x06, 0xC3, 0xC0,
+        ];
+        let packet = WMBusPacket::parse_ffb(&frame_bytes).unwrap();
+        assert_eq!(frame_bytes.to_vec(), packet.to_ffb().unwrap());
+    }
+
+    #[test]
+    pub fn can_write_ell_without_application_layer() {
+        let packet = WMBusPacket {
+            application_layer: None,
+            authentication_layer: None,
+            ext_link_layer: Some(ExtendedLinkLayer::Short { cc: 0x80, acc: 0x12 }),
+            link_layer: LinkLayer {
+                length: None,
+                control: 0x00,
+                address: MBusAddress::new(ManufacturerCode::KAM, 12345678, 0x01, DeviceType::Water),
+            },
+        };
+
+        let packet = WMBusPacket::parse_ffa(&packet.to_ffa().unwrap()).unwrap();
+        assert_eq!(Some(12), packet.link_layer.length);
+        assert_eq!(Some(FunctionCode::Ack), packet.link_layer.function_code());
+        assert!(packet.ext_link_layer == Some(ExtendedLinkLayer::Short { cc: 0x80, acc: 0x12 }));
+        assert!(packet.application_layer.is_none());
+    }
+
+    #[test]
+    pub fn parse_truncated_ell_fails() {
+        let payload = [
+            0x00, 0x44, 0x2D, 0x2C, 0x78, 0x56, 0x34, 0x12, 0x01, 0x07, // Link layer
+            0x8D, 0x20, 0x5A, 0x78, 0x56, 0x34, 0x32, 0x01, 0x02, // Long extended link layer
+        ];
+        assert!(WMBusPacket::parse_payload(&payload).is_ok());
+        for size in 11..payload.len() {
+            assert!(WMBusPacket::parse_payload(&payload[..size]).is_err());
+        }
+
+        // The frame may end right after the link layer
+        assert!(WMBusPacket::parse_payload(&payload[..10]).is_ok());
+        assert!(WMBusPacket::parse_payload(&payload[..9]).is_err());
+    }
+
+    #[test]
+    pub fn meter_and_radio_adapter_addresses() {
+        let radio_adapter = MBusAddress::new(ManufacturerCode::KAM, 12345678, 0x01, DeviceType::Other);
+        let meter = MBusAddress::new(ManufacturerCode::APT, 87654321, 0x02, DeviceType::Water);
+        let mut packet = WMBusPacket {
+            application_layer: Some(ApplicationLayer {
+                ci: 0x72,
+                data: vec![
+                    0x21, 0x43, 0x65, 0x87, 0x14, 0x86, 0x02, 0x07, 0x30, 0x00, 0x00, 0x00, 0x2F, 0x2F,
+                ],
+            }),
+            authentication_layer: None,
+            ext_link_layer: None,
+            link_layer: LinkLayer {
+                length: None,
+                control: 0x44,
+                address: radio_adapter,
+            },
+        };
+        assert_eq!(meter, packet.meter_address());
+        assert_eq!(Some(radio_adapter), packet.radio_adapter_address());
+        assert_eq!(&[0x2F, 0x2F], packet.application_layer.as_ref().unwrap().application_data().unwrap());
+
+        packet.application_layer = Some(ApplicationLayer {
+            ci: 0x7A,
+            data: vec![0x30, 0x00, 0x00, 0x00, 0x2F, 0x2F],
+        });
+        assert_eq!(radio_adapter, packet.meter_address());
+        assert_eq!(None, packet.radio_adapter_address());
+    }
+
+    const ELL_KEY: Key = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F,
+    ];
+
+    fn ell_encrypted_frame() -> Vec<u8> {
+        ell_encrypted_frame_from(&[0x2D, 0x2C, 0x78, 0x56, 0x34, 0x12, 0x01, 0x07])
+    }
+
+    /// Get the frame from the meter with the identifier bytes of the link layer address.
+    fn ell_encrypted_frame_from(identifier: &[u8; 8]) -> Vec<u8> {
+        let apl = [0x7A, 0x5A, 0x00, 0x00, 0x00, 0x2F, 0x2F, 0x04, 0x13, 0x39, 0x30, 0x00, 0x00];
+        ell_encrypted_frame_with(identifier, &apl)
+    }
+
+    /// Get the frame with the application layer encrypted in the extended link layer.
+    fn ell_encrypted_frame_with(identifier: &[u8; 8], apl: &[u8]) -> Vec<u8> {
+        let ell = [0x8D, 0x20, 0x5A, 0x78, 0x56, 0x34, 0x32]; // Long extended link layer without payload crc
+        let mut iv = [0; 16];
+        iv[0..8].copy_from_slice(identifier); // M and A as transmitted
+        iv[8] = 0x20; // CC
+        iv[9..13].copy_from_slice(&[0x78, 0x56, 0x34, 0x32]); // SN
+        // FN and BC are zero
+
+        let mut encrypted = CRC.checksum(apl).to_le_bytes().to_vec();
+        encrypted.extend_from_slice(apl);
+        SoftwareCrypto.aes_ctr(&ELL_KEY, &iv, &mut encrypted).unwrap();
+
+        let mut payload = vec![0x00, 0x44];
+        payload.extend_from_slice(identifier);
+        payload.extend_from_slice(&ell);
+        payload.extend_from_slice(&encrypted);
+        payload[0] = (payload.len() + 2 - 1) as u8;
+        let mut frame = payload.clone();
+        frame.extend_from_slice(&CRC.checksum(&payload).to_be_bytes());
+        frame
+    }
+
+    #[test]
+    pub fn can_decrypt_ell() {
+        let mut packet = WMBusPacket::parse_ffb(&ell_encrypted_frame()).unwrap();
+        let ell = packet.ext_link_layer.as_ref().unwrap();
+        assert_eq!(Some(EllEncryption::AesCtr), ell.encryption());
+        assert_eq!(0x20, ell.cc());
+        assert!(ell.communication_control().synchronized());
+        assert_eq!(0, ell.communication_control().hop_count());
+        assert_eq!(0x5A, ell.acc());
+
+        packet.decrypt_ell(&SoftwareCrypto, &ELL_KEY).unwrap();
+        let ell = packet.ext_link_layer.as_ref().unwrap();
+        assert_eq!(Some(EllEncryption::None), ell.encryption());
+        assert_eq!(Some(0x12345678), ell.sn());
+        let apl = packet.application_layer.unwrap();
+        assert_eq!(0x7A, apl.ci);
+        assert_eq!(
+            vec![0x5A, 0x00, 0x00, 0x00, 0x2F, 0x2F, 0x04, 0x13, 0x39, 0x30, 0x00, 0x00],
+            apl.data
+        );
+    }
+
+    #[test]
+    pub fn can_decrypt_and_write_ell_with_diehl_layout() {
+        // The identifier of a Sharky 775 heat meter with version and type before the serial number
+        let identifier = [0x24, 0x23, 0x20, 0x04, 0x69, 0x02, 0x71, 0x47];
+        let frame = ell_encrypted_frame_from(&identifier);
+        let mut packet = WMBusPacket::parse_ffb(&frame).unwrap();
+        assert_eq!(47710269, packet.link_layer.address.serial_number.decode());
+        assert_eq!(frame, packet.to_ffb().unwrap());
+
+        packet.decrypt_ell(&SoftwareCrypto, &ELL_KEY).unwrap();
+        packet.encrypt_ell(&SoftwareCrypto, &ELL_KEY).unwrap();
+        assert_eq!(frame, packet.to_ffb().unwrap());
+    }
+
+    #[test]
+    pub fn decrypt_ell_with_wrong_key_fails() {
+        let mut packet = WMBusPacket::parse_ffb(&ell_encrypted_frame()).unwrap();
+        let data = packet.application_layer.as_ref().unwrap().data.clone();
+
+        assert!(packet.decrypt_ell(&SoftwareCrypto, &[0; 16]).is_err());
+        assert_eq!(
+            Some(EllEncryption::AesCtr),
+            packet.ext_link_layer.as_ref().unwrap().encryption()
+        );
+        assert_eq!(data, packet.application_layer.as_ref().unwrap().data);
+    }
+
+    #[test]
+    pub fn decrypt_ell_with_invalid_afl_fails() {
+        // The payload crc matches, but the authentication and fragmentation layer is truncated
+        let frame = ell_encrypted_frame_with(&[0x2D, 0x2C, 0x78, 0x56, 0x34, 0x12, 0x01, 0x07], &[0x90, 0x05, 0x00]);
+        let mut packet = WMBusPacket::parse_ffb(&frame).unwrap();
+
+        assert!(packet.decrypt_ell(&SoftwareCrypto, &ELL_KEY).is_err());
+        assert_eq!(
+            Some(EllEncryption::AesCtr),
+            packet.ext_link_layer.as_ref().unwrap().encryption()
+        );
+        assert_eq!(frame, packet.to_ffb().unwrap());
+    }
+
+    fn mode5_encrypted_packet() -> WMBusPacket {
+        let mut payload = vec![
+            0x00, 0x44, 0x2D, 0x2C, 0x78, 0x56, 0x34, 0x12, 0x01, 0x07, // Link layer
+            0x7A, 0x5A, 0x00, 0x10, 0x05, // Short transport layer with one encrypted block
+            0xBA, 0xB7, 0xC6, 0x63, 0x1D, 0x10, 0xCB, 0x9E, 0x9D, 0xFC, 0x0F, 0x4D, 0x1C, 0x7A, 0x8C, 0x0A,
+            0x0F, 0x01, 0x02, // Unencrypted manufacturer specific data
+        ];
+        payload[0] = (payload.len() - 1) as u8;
+        WMBusPacket::parse_payload(&payload).unwrap()
+    }
+
+    #[test]
+    pub fn can_decrypt_mode5() {
+        let mut packet = mode5_encrypted_packet();
+        let configuration = packet.transport_layer().unwrap().unwrap().configuration();
+        assert_eq!(Some(SecurityMode::Mode5), configuration.security_mode());
+        assert_eq!(1, configuration.encrypted_blocks);
+
+        packet.decrypt_tpl(&SoftwareCrypto, &ELL_KEY).unwrap();
+        let tpl = packet.transport_layer().unwrap().unwrap();
+        assert_eq!(Some(SecurityMode::Mode0), tpl.configuration().security_mode());
+        assert_eq!(0x5A, tpl.acc());
+        let apl = packet.application_layer.as_ref().unwrap();
+        assert_eq!(
+            &[
+                0x2F, 0x2F, 0x04, 0x13, 0x39, 0x30, 0x00, 0x00, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F,
+                0x0F, 0x01, 0x02,
+            ],
+            apl.application_data().unwrap()
+        );
+
+        let records = apl.data_records().unwrap();
+        assert_eq!(1, records.records.len());
+        assert_eq!(Some(vec![0x01, 0x02]), records.manufacturer_data);
+    }
+
+    #[test]
+    pub fn can_decrypt_mode5_with_diehl_layout() {
+        // The identifier of a Sharky 775 heat meter with version and type before the serial number
+        let identifier = [0x24, 0x23, 0x20, 0x04, 0x69, 0x02, 0x71, 0x47];
+        let mut iv = [0x5A; 16];
+        iv[0..8].copy_from_slice(&identifier);
+        let mut encrypted = [
+            0x2F, 0x2F, 0x04, 0x13, 0x39, 0x30, 0x00, 0x00, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F,
+        ];
+        SoftwareCrypto.aes_cbc_encrypt(&ELL_KEY, &iv, &mut encrypted).unwrap();
+
+        let mut payload = vec![0x00, 0x44];
+        payload.extend_from_slice(&identifier);
+        payload.extend_from_slice(&[0x7A, 0x5A, 0x00, 0x10, 0x05]);
+        payload.extend_from_slice(&encrypted);
+        payload[0] = (payload.len() - 1) as u8;
+        let mut packet = WMBusPacket::parse_payload(&payload).unwrap();
+
+        packet.decrypt_tpl(&SoftwareCrypto, &ELL_KEY).unwrap();
+        let records = packet.application_layer.unwrap().data_records().unwrap();
+        assert_eq!(Ok(Value::Integer(12345)), records.records[0].value());
+    }
+
+    #[test]
+    pub fn decrypt_mode5_with_wrong_key_fails() {
+        let mut packet = mode5_encrypted_packet();
+        let data = packet.application_layer.as_ref().unwrap().data.clone();
+
+        assert!(packet.decrypt_tpl(&SoftwareCrypto, &[0; 16]).is_err());
+        assert_eq!(data, packet.application_layer.as_ref().unwrap().data);
+    }
+
+    fn mode7_encrypted_payload() -> Vec<u8> {
+        let mut payload = vec![
+            0x00, 0x44, 0x2D, 0x2C, 0x78, 0x56, 0x34, 0x12, 0x01, 0x07, // Link layer
+            0x90, 0x0F, 0x00, 0x2C, 0x25, 0x02, 0x01, 0x00, 0x00, // AFL with MCL and MCR
+            0x0C, 0x28, 0x90, 0x3B, 0x8D, 0xF3, 0xB5, 0xCB, // AFL MAC
+            0x7A, 0x5A, 0x00, 0x10, 0x07, 0x10, // Short transport layer with one encrypted block
+            0xD3, 0xD6, 0x65, 0xDA, 0x9D, 0x93, 0x8B, 0x27, 0xA0, 0x38, 0xFA, 0xB2, 0x8E, 0x3C, 0xB0, 0x0B,
+            0x0F, 0x01, 0x02, // Unencrypted manufacturer specific data
+        ];
+        payload[0] = (payload.len() - 1) as u8;
+        payload
+    }
+
+    #[test]
+    pub fn can_decrypt_mode7() {
+        let mut packet = WMBusPacket::parse_payload(&mode7_encrypted_payload()).unwrap();
+        let apl = packet.application_layer.as_ref().unwrap();
+        assert_eq!(Some(0x0102), packet.authentication_layer.as_ref().unwrap().mcr);
+        let configuration = apl.transport_layer().unwrap().unwrap().configuration();
+        assert_eq!(Some(SecurityMode::Mode7), configuration.security_mode());
+        assert!(apl.data_records().is_err());
+
+        packet.decrypt_tpl(&SoftwareCrypto, &ELL_KEY).unwrap();
+        let apl = packet.application_layer.as_ref().unwrap();
+        let tpl = apl.transport_layer().unwrap().unwrap();
+        assert_eq!(Some(SecurityMode::Mode0), tpl.configuration().security_mode());
+        assert_eq!(None, tpl.configuration().extension);
+        assert_eq!(
+            &[
+                0x2F, 0x2F, 0x04, 0x13, 0x39, 0x30, 0x00, 0x00, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F,
+                0x0F, 0x01, 0x02,
+            ],
+            apl.application_data().unwrap()
+        );
+
+        let records = apl.data_records().unwrap();
+        assert_eq!(1, records.records.len());
+        assert_eq!(Some(vec![0x01, 0x02]), records.manufacturer_data);
+    }
+
+    #[test]
+    pub fn decrypt_mode7_with_invalid_mac_fails() {
+        let mut payload = mode7_encrypted_payload();
+        // Tamper with the unencrypted data
+        *payload.last_mut().unwrap() ^= 0x01;
+        let mut packet = WMBusPacket::parse_payload(&payload).unwrap();
+        let data = packet.application_layer.as_ref().unwrap().data.clone();
+
+        assert!(packet.decrypt_tpl(&SoftwareCrypto, &ELL_KEY).is_err());
+        assert_eq!(data, packet.application_layer.as_ref().unwrap().data);
+    }
+
+    /// Get a mode 10 message encrypted with an independent AES-GCM implementation,
+    /// where the IV is 2D 2C 78 56 34 12 01 07 02 01 00 00 and the authenticated data is 28 02 01 00 00 7A 5A 00 00 0A.
+    pub fn mode10_encrypted_payload() -> Vec<u8> {
+        let mut payload = vec![
+            0x00, 0x44, 0x2D, 0x2C, 0x78, 0x56, 0x34, 0x12, 0x01, 0x07, // Link layer
+            0x90, 0x13, 0x00, 0x2C, 0x28, 0x02, 0x01, 0x00, 0x00, // AFL with MCL and MCR
+            0x69, 0x5E, 0xC8, 0x6E, 0x5A, 0x14, 0xDC, 0xEF, 0x7C, 0x16, 0xA2, 0xE5, // AFL GMAC
+            0x7A, 0x5A, 0x00, 0x00, 0x0A, // Short transport layer
+            0x96, 0x80, 0xF2, 0x85, 0x16, 0x85, 0x1C, 0x95, 0xF1,
+        ];
+        payload[0] = (payload.len() - 1) as u8;
+        payload
+    }
+
+    #[test]
+    pub fn can_decrypt_mode10() {
+        let mut packet = WMBusPacket::parse_payload(&mode10_encrypted_payload()).unwrap();
+        let configuration = packet.transport_layer().unwrap().unwrap().configuration();
+        assert_eq!(Some(SecurityMode::Mode10), configuration.security_mode());
+
+        packet.decrypt_tpl(&SoftwareCrypto, &ELL_KEY).unwrap();
+        let apl = packet.application_layer.as_ref().unwrap();
+        let tpl = apl.transport_layer().unwrap().unwrap();
+        assert_eq!(Some(SecurityMode::Mode0), tpl.configuration().security_mode());
+        assert_eq!(
+            &[0x04, 0x13, 0x39, 0x30, 0x00, 0x00, 0x0F, 0x01, 0x02],
+            apl.application_data().unwrap()
+        );
+    }
+
+    #[test]
+    pub fn decrypt_mode10_with_invalid_tag_fails() {
+        let mut payload = mode10_encrypted_payload();
+        // Tamper with the authenticated access number
+        payload[32] ^= 0x01;
+        let mut packet = WMBusPacket::parse_payload(&payload).unwrap();
+        let data = packet.application_layer.as_ref().unwrap().data.clone();
+
+        assert!(packet.decrypt_tpl(&SoftwareCrypto, &ELL_KEY).is_err());
+        assert_eq!(data, packet.application_layer.as_ref().unwrap().data);
+    }
+
+    #[test]
+    pub fn can_decrypt_oms_mode5_example() {
+        // The security profile A example of OMS Specification Volume 2, Annex N
+        let key = [
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F, 0x11,
+        ];
+        let payload = [
+            0x2E, 0x44, 0x93, 0x15, 0x78, 0x56, 0x34, 0x12, 0x33, 0x03, // Link layer
+            0x7A, 0x2A, 0x00, 0x20, 0x05, // Short transport layer with two encrypted blocks
+            0x59, 0x23, 0xC9, 0x5A, 0xAA, 0x26, 0xD1, 0xB2, 0xE7, 0x49, 0x3B, 0x01, 0x3E, 0xC4, 0xA6, 0xF6,
+            0xD3, 0x52, 0x9B, 0x52, 0x0E, 0xDF, 0xF0, 0xEA, 0x6D, 0xEF, 0xC9, 0x9D, 0x6D, 0x69, 0xEB, 0xF3,
+        ];
+        let mut packet = WMBusPacket::parse_payload(&payload).unwrap();
+
+        packet.decrypt_tpl(&SoftwareCrypto, &key).unwrap();
+        let apl = packet.application_layer.unwrap();
+        assert_eq!(
+            &[
+                0x2F, 0x2F, 0x0C, 0x14, 0x27, 0x04, 0x85, 0x02, 0x04, 0x6D, 0x32, 0x37, 0x1F, 0x15, 0x02, 0xFD,
+                0x17, 0x00, 0x00, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F,
+            ],
+            apl.application_data().unwrap()
+        );
+        let records = apl.data_records().unwrap();
+        assert_eq!(Ok(Value::Bcd(2850427)), records.records[0].value());
+    }
+
+    /// The identifier of a Sharky 775 heat meter, where the identification number field holds the version and type.
+    const DIEHL_IDENTIFIER: [u8; 8] = [0x24, 0x23, 0x20, 0x04, 0x69, 0x02, 0x71, 0x47];
+
+    #[test]
+    pub fn mode7_key_derivation_and_mac_input() {
+        // The KDF-A input is the derivation constant, the message counter, the identification number field and 0x07 padding
+        let kdf_input = |constant| {
+            let mut input = [0x07; 16];
+            input[0] = constant;
+            input[1..5].copy_from_slice(&[0x02, 0x01, 0x00, 0x00]);
+            input[5..9].copy_from_slice(&DIEHL_IDENTIFIER[2..6]);
+            input
+        };
+        let enc_key = SoftwareCrypto.aes_cmac(&ELL_KEY, &kdf_input(0x00)).unwrap();
+        let mac_key = SoftwareCrypto.aes_cmac(&ELL_KEY, &kdf_input(0x01)).unwrap();
+
+        // The data is encrypted with AES-128-CBC and a zero IV
+        let mut tpl = vec![0x7A, 0x5A, 0x00, 0x10, 0x07, 0x10];
+        let mut encrypted = [
+            0x2F, 0x2F, 0x04, 0x13, 0x39, 0x30, 0x00, 0x00, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F,
+        ];
+        SoftwareCrypto.aes_cbc_encrypt(&enc_key, &[0; 16], &mut encrypted).unwrap();
+        tpl.extend_from_slice(&encrypted);
+
+        // The MAC input is the message control, the message counter and the message starting with its CI field
+        let mut mac_input = vec![0x25, 0x02, 0x01, 0x00, 0x00];
+        mac_input.extend_from_slice(&tpl);
+        let mac = SoftwareCrypto.aes_cmac(&mac_key, &mac_input).unwrap();
+
+        let mut payload = vec![0x00, 0x44];
+        payload.extend_from_slice(&DIEHL_IDENTIFIER);
+        payload.extend_from_slice(&[0x90, 0x0F, 0x00, 0x2C, 0x25, 0x02, 0x01, 0x00, 0x00]);
+        payload.extend_from_slice(&mac[..8]);
+        payload.extend_from_slice(&tpl);
+        payload[0] = (payload.len() - 1) as u8;
+        let mut packet = WMBusPacket::parse_payload(&payload).unwrap();
+        assert!(packet.verify_mac(&SoftwareCrypto, &ELL_KEY).is_ok());
+
+        packet.decrypt_tpl(&SoftwareCrypto, &ELL_KEY).unwrap();
+        let records = packet.application_layer.unwrap().data_records().unwrap();
+        assert_eq!(Ok(Value::Integer(12345)), records.records[0].value());
+    }
+
+    #[test]
+    pub fn can_decrypt_mode10_with_diehl_layout() {
+        // Encrypted with an independent AES-GCM implementation, where the IV is the address as transmitted
+        // followed by the message counter, i.e. 24 23 20 04 69 02 71 47 02 01 00 00,
+        // and the authenticated data is the message control, the message counter and the transport layer header
+        let mut payload = vec![0x00, 0x44];
+        payload.extend_from_slice(&DIEHL_IDENTIFIER);
+        payload.extend_from_slice(&[
+            0x90, 0x13, 0x00, 0x2C, 0x28, 0x02, 0x01, 0x00, 0x00, // AFL with MCL and MCR
+            0x4B, 0x2D, 0x8C, 0x1C, 0xEC, 0x15, 0xF6, 0x09, 0x8A, 0x2A, 0xDB, 0x3B, // AFL GMAC
+            0x7A, 0x5A, 0x00, 0x00, 0x0A, // Short transport layer
+            0x44, 0x1F, 0xA0, 0xEE, 0x8E, 0x48,
+        ]);
+        payload[0] = (payload.len() - 1) as u8;
+        let mut packet = WMBusPacket::parse_payload(&payload).unwrap();
+
+        packet.decrypt_tpl(&SoftwareCrypto, &ELL_KEY).unwrap();
+        assert_eq!(
+            &[0x04, 0x13, 0x39, 0x30, 0x00, 0x00],
+            packet.application_layer.unwrap().application_data().unwrap()
+        );
+    }
+
+    fn plaintext_packet() -> WMBusPacket {
+        let mut payload = vec![
+            0x00, 0x44, 0x2D, 0x2C, 0x78, 0x56, 0x34, 0x12, 0x01, 0x07, // Link layer
+            0x7A, 0x5A, 0x00, 0x00, 0x00, // Short transport layer
+            0x04, 0x13, 0x39, 0x30, 0x00, 0x00,
+        ];
+        payload[0] = (payload.len() - 1) as u8;
+        WMBusPacket::parse_payload(&payload).unwrap()
+    }
+
+    #[test]
+    pub fn can_write_data_records() {
+        let mut packet = plaintext_packet();
+        let apl = packet.application_layer.as_mut().unwrap();
+        let mut records = apl.data_records().unwrap();
+        records.records.push(
+            TypedRecord {
+                value_information: ValueInformation::new(Quantity::OperatingTime, Unit::Day, 0),
+                function: Function::Instantaneous,
+                storage_number: 0,
+                tariff: 0,
+                subunit: 0,
+                modifiers: Vec::new(),
+                value: Value::Integer(365),
+            }
+            .encode()
+            .unwrap(),
+        );
+        apl.set_data_records(&records).unwrap();
+        assert_eq!(
+            &[0x04, 0x13, 0x39, 0x30, 0x00, 0x00, 0x02, 0x27, 0x6D, 0x01],
+            apl.application_data().unwrap()
+        );
+        assert_eq!(records, apl.data_records().unwrap());
+
+        // The transport layer header is kept
+        assert_eq!(0x5A, apl.transport_layer().unwrap().unwrap().acc());
+    }
+
+    #[test]
+    pub fn can_encrypt_mode5() {
+        let mut packet = plaintext_packet();
+        packet.encrypt_mode5(&SoftwareCrypto, &ELL_KEY).unwrap();
+        let apl = packet.application_layer.as_ref().unwrap();
+        assert_eq!(
+            vec![
+                0x5A, 0x00, 0x10, 0x05, // Short transport layer with one encrypted block
+                0xBA, 0xB7, 0xC6, 0x63, 0x1D, 0x10, 0xCB, 0x9E, 0x9D, 0xFC, 0x0F, 0x4D, 0x1C, 0x7A, 0x8C, 0x0A,
+            ],
+            apl.data
+        );
+
+        let mut packet = WMBusPacket::parse_ffa(&packet.to_ffa().unwrap()).unwrap();
+        packet.decrypt_tpl(&SoftwareCrypto, &ELL_KEY).unwrap();
+        assert_eq!(
+            &[0x2F, 0x2F, 0x04, 0x13, 0x39, 0x30, 0x00, 0x00, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F],
+            packet.application_layer.as_ref().unwrap().application_data().unwrap()
+        );
+    }
+
+    #[test]
+    pub fn can_encrypt_mode7() {
+        let mut packet = plaintext_packet();
+        packet.encrypt_mode7(&SoftwareCrypto, &ELL_KEY, 0x0102).unwrap();
+        let apl = packet.application_layer.as_ref().unwrap();
+        assert_eq!(CiField::ApplicationLayer(TransportHeader::Short), apl.ci_field());
+        assert_eq!(Some(0x0102), packet.authentication_layer.as_ref().unwrap().mcr);
+        let configuration = apl.transport_layer().unwrap().unwrap().configuration();
+        assert_eq!(Some(SecurityMode::Mode7), configuration.security_mode());
+        assert_eq!(Some(ConfigurationFieldExtension::new(1, 0)), configuration.extension);
+        assert_eq!(
+            &[0xD3, 0xD6, 0x65, 0xDA, 0x9D, 0x93, 0x8B, 0x27, 0xA0, 0x38, 0xFA, 0xB2, 0x8E, 0x3C, 0xB0, 0x0B],
+            apl.application_data().unwrap()
+        );
+
+        let mut packet = WMBusPacket::parse_ffb(&packet.to_ffb().unwrap()).unwrap();
+        packet.decrypt_tpl(&SoftwareCrypto, &ELL_KEY).unwrap();
+        assert_eq!(
+            &[0x2F, 0x2F, 0x04, 0x13, 0x39, 0x30, 0x00, 0x00, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F, 0x2F],
+            packet.application_layer.as_ref().unwrap().application_data().unwrap()
+        );
+    }
+
+    #[test]
+    pub fn encrypt_already_encrypted_fails() {
+        let mut packet = plaintext_packet();
+        packet.encrypt_mode5(&SoftwareCrypto, &ELL_KEY).unwrap();
+        assert!(packet.encrypt_mode5(&SoftwareCrypto, &ELL_KEY).is_err());
+        assert!(packet.encrypt_mode7(&SoftwareCrypto, &ELL_KEY, 0).is_err());
+    }
+
+    #[test]
+    pub fn can_encrypt_ell() {
+        let mut packet = WMBusPacket::parse_ffb(&ell_encrypted_frame()).unwrap();
+        packet.decrypt_ell(&SoftwareCrypto, &ELL_KEY).unwrap();
+
+        packet.encrypt_ell(&SoftwareCrypto, &ELL_KEY).unwrap();
+        assert_eq!(
+            Some(EllEncryption::AesCtr),
+            packet.ext_link_layer.as_ref().unwrap().encryption()
+        );
+        assert_eq!(ell_encrypted_frame(), packet.to_ffb().unwrap());
+    }
+
+    #[test]
+    pub fn decrypt_with_key_store() {
+        let address = MBusAddress::new(ManufacturerCode::KAM, 12345678, 0x01, DeviceType::Water);
+        let mut key_store = MemoryKeyStore::new();
+        key_store.add(&address, [0; 16]);
+        key_store.add(&address, ELL_KEY);
+
+        let mut packet = mode5_encrypted_packet();
+        assert_eq!(
+            UsedKeys {
+                ell: None,
+                tpl: Some(1)
+            },
+            packet.decrypt(&SoftwareCrypto, &key_store).unwrap()
+        );
+        let configuration = packet.transport_layer().unwrap().unwrap().configuration();
+        assert_eq!(Some(SecurityMode::Mode0), configuration.security_mode());
+
+        let mut packet = WMBusPacket::parse_ffb(&ell_encrypted_frame()).unwrap();
+        assert_eq!(
+            UsedKeys {
+                ell: Some(1),
+                tpl: None
+            },
+            packet.decrypt(&SoftwareCrypto, &key_store).unwrap()
+        );
+
+        let mut packet = mode5_encrypted_packet();
+        assert!(packet.decrypt(&SoftwareCrypto, &MemoryKeyStore::new()).is_err());
+    }
+
+    #[test]
+    pub fn failed_decrypt_leaves_packet_unchanged() {
+        let address = MBusAddress::new(ManufacturerCode::KAM, 12345678, 0x01, DeviceType::Water);
+        let mut key_store = MemoryKeyStore::new();
+        key_store.add(&address, ELL_KEY);
+
+        // The transport layer block is not encrypted with any known key
+        let apl = [
+            0x7A, 0x5A, 0x00, 0x10, 0x05, // Short transport layer with one encrypted block
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F,
+        ];
+        let frame = ell_encrypted_frame_with(&[0x2D, 0x2C, 0x78, 0x56, 0x34, 0x12, 0x01, 0x07], &apl);
+        let mut packet = WMBusPacket::parse_ffb(&frame).unwrap();
+        assert!(packet.clone().decrypt_ell(&SoftwareCrypto, &ELL_KEY).is_ok());
+
+        assert!(packet.decrypt(&SoftwareCrypto, &key_store).is_err());
+        assert_eq!(
+            Some(EllEncryption::AesCtr),
+            packet.ext_link_layer.as_ref().unwrap().encryption()
+        );
+        assert_eq!(frame, packet.to_ffb().unwrap());
+    }
+
+    #[test]
+    pub fn decrypt_with_key_handles() {
+        let crypto = MockCrypto::new();
+        let mut key_store = MemoryKeyStore::new();
+        key_store.add(
+            &MBusAddress::new(ManufacturerCode::KAM, 12345678, 0x01, DeviceType::Water),
+            crypto.add_key(ELL_KEY),
+        );
+
+        let mut packet = WMBusPacket::parse_payload(&mode7_encrypted_payload()).unwrap();
+        assert_eq!(
+            UsedKeys {
+                ell: None,
+                tpl: Some(0)
+            },
+            packet.decrypt(&crypto, &key_store).unwrap()
+        );
+        // The two key derivations, the MAC and the decryption
+        assert_eq!(4, crypto.operations.get());
     }
 }